@@ -0,0 +1,256 @@
+use ::anyhow::{bail, Context, Result};
+use ::image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, Rgba, RgbaImage,
+};
+use ::std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Streams snapshots of the image to an animated GIF or PNG as pixels are
+/// placed
+///
+/// Pixels that haven't been placed yet are transparent. Each frame is encoded
+/// as soon as it's taken, so only the latest snapshot is kept in memory.
+pub(crate) struct Animation {
+    encoder: Encoder,
+    canvas: RgbaImage,
+    frame_every: u32,
+    placed_since_frame: u32,
+}
+
+/// Settings for the timing of an animation
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct Pacing {
+    /// Pixels placed between frames, overriding `frames`
+    pub(crate) frame_every: Option<u32>,
+    /// Roughly how many frames to take over the whole image
+    pub(crate) frames: u32,
+    /// Frames per second when played back
+    pub(crate) fps: u32,
+}
+
+enum Encoder {
+    Gif {
+        encoder: GifEncoder<BufWriter<File>>,
+        delay: Delay,
+    },
+    Png(Apng),
+}
+
+impl Animation {
+    /// Start writing an animation of an image with the given dimensions
+    ///
+    /// Paths ending in `.png` get an animated PNG, and anything else a GIF.
+    pub(crate) fn create(
+        path: &Path,
+        (width, height): (u32, u32),
+        pacing: Pacing,
+    ) -> Result<Animation> {
+        if pacing.frames == 0 || pacing.fps == 0 {
+            bail!("Animations need at least 1 frame and 1 frame per second");
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create animation file {}", path.display()))?;
+        let is_png = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        let encoder = if is_png {
+            Encoder::Png(Apng::new(BufWriter::new(file), pacing.fps)?)
+        } else {
+            // the fastest quantizer speed, since every frame has to be quantized
+            let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 30);
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .context("Failed to set animation to loop")?;
+            Encoder::Gif {
+                encoder,
+                delay: Delay::from_numer_denom_ms(1000, pacing.fps),
+            }
+        };
+        let frame_every = pacing
+            .frame_every
+            .unwrap_or_else(|| (width * height).div_ceil(pacing.frames).max(1));
+        Ok(Animation {
+            encoder,
+            canvas: RgbaImage::new(width, height),
+            frame_every,
+            placed_since_frame: 0,
+        })
+    }
+
+    /// Record a placed pixel, writing a frame if enough have been placed
+    pub(crate) fn place(&mut self, (row, col): (u32, u32), colour: Rgba<u8>) -> Result<()> {
+        self.canvas.put_pixel(col, row, colour);
+        self.placed_since_frame += 1;
+        if self.placed_since_frame >= self.frame_every {
+            self.write_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Write the finished image as the last frame, if it isn't already
+    pub(crate) fn finish(mut self) -> Result<()> {
+        if self.placed_since_frame > 0 {
+            self.write_frame()?;
+        }
+        match self.encoder {
+            Encoder::Gif { .. } => Ok(()),
+            Encoder::Png(apng) => apng.finish(),
+        }
+    }
+
+    fn write_frame(&mut self) -> Result<()> {
+        self.placed_since_frame = 0;
+        match &mut self.encoder {
+            Encoder::Gif { encoder, delay } => encoder
+                .encode_frame(Frame::from_parts(self.canvas.clone(), 0, 0, *delay))
+                .context("Failed to write animation frame"),
+            Encoder::Png(apng) => apng.write_frame(&self.canvas),
+        }
+    }
+}
+
+/// Writes an animated PNG one frame at a time
+///
+/// Each frame is encoded as a PNG of its own, whose image data is copied into
+/// the animation. The frame count comes before the frames, so it's filled in
+/// once the animation is finished.
+struct Apng {
+    file: BufWriter<File>,
+    fps: u16,
+    frames: u32,
+    /// Sequence number of the next frame control or frame data chunk
+    sequence: u32,
+}
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+/// Where the `acTL` chunk starts, after the signature and `IHDR` chunk
+const ACTL_OFFSET: u64 = 8 + 25;
+
+impl Apng {
+    fn new(mut file: BufWriter<File>, fps: u32) -> Result<Apng> {
+        file.write_all(PNG_SIGNATURE)
+            .context("Failed to write animation header")?;
+        Ok(Apng {
+            file,
+            fps: fps.try_into().unwrap_or(u16::MAX),
+            frames: 0,
+            sequence: 0,
+        })
+    }
+
+    fn write_frame(&mut self, canvas: &RgbaImage) -> Result<()> {
+        let mut png = Vec::new();
+        {
+            let mut encoder = ::png::Encoder::new(&mut png, canvas.width(), canvas.height());
+            encoder.set_color(::png::ColorType::RGBA);
+            encoder.set_depth(::png::BitDepth::Eight);
+            encoder.set_compression(::png::Compression::Fast);
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(canvas.as_raw()))
+                .context("Failed to encode animation frame")?;
+        }
+        let chunks = chunks(&png)?;
+        if self.frames == 0 {
+            let header = chunks
+                .iter()
+                .find(|(name, _)| name == b"IHDR")
+                .context("Encoded frame has no header")?;
+            write_chunk(&mut self.file, *b"IHDR", header.1)?;
+            write_chunk(&mut self.file, *b"acTL", &animation_control(0))?;
+        }
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&self.next_sequence().to_be_bytes());
+        control.extend_from_slice(&canvas.width().to_be_bytes());
+        control.extend_from_slice(&canvas.height().to_be_bytes());
+        // frame offset
+        control.extend_from_slice(&[0; 8]);
+        // delay as a fraction of a second
+        control.extend_from_slice(&1u16.to_be_bytes());
+        control.extend_from_slice(&self.fps.to_be_bytes());
+        // leave the frame in place, and replace the area it covers
+        control.extend_from_slice(&[0, 0]);
+        write_chunk(&mut self.file, *b"fcTL", &control)?;
+        for (_, data) in chunks.iter().filter(|(name, _)| name == b"IDAT") {
+            if self.frames == 0 {
+                write_chunk(&mut self.file, *b"IDAT", data)?;
+            } else {
+                let mut frame_data = Vec::with_capacity(4 + data.len());
+                frame_data.extend_from_slice(&self.next_sequence().to_be_bytes());
+                frame_data.extend_from_slice(data);
+                write_chunk(&mut self.file, *b"fdAT", &frame_data)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// End the animation and fill in its frame count
+    fn finish(mut self) -> Result<()> {
+        if self.frames == 0 {
+            bail!("Animation has no frames");
+        }
+        write_chunk(&mut self.file, *b"IEND", &[])?;
+        let mut file = self
+            .file
+            .into_inner()
+            .context("Failed to finish writing animation")?;
+        file.seek(SeekFrom::Start(ACTL_OFFSET))
+            .context("Failed to go back to animation frame count")?;
+        write_chunk(&mut file, *b"acTL", &animation_control(self.frames))
+    }
+
+    fn next_sequence(&mut self) -> u32 {
+        self.sequence += 1;
+        self.sequence - 1
+    }
+}
+
+/// Contents of an `acTL` chunk for an endlessly looping animation
+fn animation_control(frames: u32) -> [u8; 8] {
+    let mut control = [0; 8];
+    control[..4].copy_from_slice(&frames.to_be_bytes());
+    control
+}
+
+/// The names and contents of the chunks of a PNG
+fn chunks(png: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let mut rest = png
+        .strip_prefix(PNG_SIGNATURE)
+        .context("Encoded frame isn't a PNG")?;
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        let (length, name) = match rest {
+            [a, b, c, d, e, f, g, h, ..] => (
+                u32::from_be_bytes([*a, *b, *c, *d]) as usize,
+                [*e, *f, *g, *h],
+            ),
+            _ => bail!("Encoded frame has a truncated chunk"),
+        };
+        let data = rest
+            .get(8..8 + length)
+            .context("Encoded frame has a truncated chunk")?;
+        chunks.push((name, data));
+        // skip the checksum too
+        rest = rest.get(12 + length..).unwrap_or_default();
+    }
+    Ok(chunks)
+}
+
+fn write_chunk<W: Write>(mut writer: W, name: [u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = ::crc32fast::Hasher::new();
+    crc.update(&name);
+    crc.update(data);
+    let length = u32::try_from(data.len()).context("Animation chunk is too long")?;
+    writer
+        .write_all(&length.to_be_bytes())
+        .and_then(|()| writer.write_all(&name))
+        .and_then(|()| writer.write_all(data))
+        .and_then(|()| writer.write_all(&crc.finalize().to_be_bytes()))
+        .context("Failed to write animation")
+}
@@ -0,0 +1,838 @@
+use ::anyhow::{bail, Result};
+use ::clap::ArgEnum;
+use ::image::Rgb;
+use ::std::{
+    fmt::Display,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// Which neighbours of a pixel it can be joined to
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum Connectivity {
+    /// Only north, east, south and west neighbours
+    #[clap(name = "4")]
+    Four,
+    /// Orthogonal and diagonal neighbours
+    #[clap(name = "8")]
+    Eight,
+}
+
+/// Number of bits per colour channel
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum Depth {
+    /// 8 bits per channel
+    #[clap(name = "8")]
+    Eight,
+    /// 16 bits per channel
+    #[clap(name = "16")]
+    Sixteen,
+}
+
+/// Which halves of the image are mirror images of each other
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum Symmetry {
+    /// No mirroring
+    None,
+    /// The right half mirrors the left
+    Horizontal,
+    /// The bottom half mirrors the top
+    Vertical,
+    /// Each quarter mirrors the top left one
+    Quad,
+}
+
+impl Symmetry {
+    /// Whether columns are mirrored
+    pub(crate) fn horizontal(self) -> bool {
+        matches!(self, Symmetry::Horizontal | Symmetry::Quad)
+    }
+
+    /// Whether rows are mirrored
+    pub(crate) fn vertical(self) -> bool {
+        matches!(self, Symmetry::Vertical | Symmetry::Quad)
+    }
+}
+
+/// Where progress bars are drawn
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ProgressOutput {
+    /// Bars on stderr, if it's a terminal
+    Stderr,
+    /// Bars on stdout, if it's a terminal
+    Stdout,
+    /// No progress at all
+    Hidden,
+    /// Plain lines of how far each stage has got, written to a file every
+    /// second, since there's no terminal to redraw bars on
+    File(PathBuf),
+}
+
+/// Whether to log each stage of generation on stderr
+///
+/// Log lines are stamped with the time since `start`, which is usually when
+/// the program started.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Verbosity {
+    start: Option<Instant>,
+}
+
+impl Verbosity {
+    /// Log every stage, timed from `start`
+    pub fn verbose(start: Instant) -> Verbosity {
+        Verbosity { start: Some(start) }
+    }
+
+    /// Log nothing
+    pub fn silent() -> Verbosity {
+        Verbosity { start: None }
+    }
+
+    pub fn is_verbose(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// A message with its timestamp, if logging
+    pub(crate) fn line(&self, message: impl Display) -> Option<String> {
+        self.start
+            .map(|start| format!("[{:9.3}s] {}", start.elapsed().as_secs_f64(), message))
+    }
+
+    /// Print a message with its timestamp on stderr, if logging
+    pub fn log(&self, message: impl Display) {
+        if let Some(line) = self.line(message) {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Lets another thread, like a Ctrl-C handler, stop an image partway through
+/// colouring
+///
+/// Once stopped, no more pixels are placed, and the image is returned with
+/// just the ones placed so far. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    state: Arc<InterruptState>,
+}
+
+#[derive(Debug, Default)]
+struct InterruptState {
+    stopped: AtomicBool,
+    colouring: AtomicBool,
+}
+
+impl Interrupt {
+    pub fn new() -> Interrupt {
+        Interrupt::default()
+    }
+
+    /// Stop placing pixels
+    ///
+    /// Returns whether pixels are being placed, so the image will be returned
+    /// unfinished. If not, there's nothing to save yet, or it's been stopped
+    /// already. Stopping before colouring starts makes generation fail once
+    /// the tree is ready. Only atomics are touched, so it's safe to call from
+    /// a signal handler.
+    pub fn stop(&self) -> bool {
+        let first = !self.state.stopped.swap(true, Ordering::SeqCst);
+        first && self.state.colouring.load(Ordering::SeqCst)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.state.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Mark pixels as being placed, unless already stopped
+    pub(crate) fn begin_colouring(&self) -> bool {
+        self.state.colouring.store(true, Ordering::SeqCst);
+        !self.state.stopped.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn end_colouring(&self) {
+        self.state.colouring.store(false, Ordering::SeqCst);
+    }
+}
+
+impl PartialEq for Interrupt {
+    fn eq(&self, other: &Interrupt) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for Interrupt {}
+
+/// Colour of the first pixel
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StartColour {
+    /// A specific colour
+    Fixed(Rgb<u8>),
+    /// A colour picked by the random number generator
+    Random,
+}
+
+/// What happens when a randomly perturbed colour channel runs past the end of
+/// its range
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum WrapMode {
+    /// Stop at the end of the range, so colours bunch up at the extremes
+    Saturate,
+    /// Carry on from the other end of the range
+    Wrap,
+    /// Bounce back off the end of the range, so every value is equally likely
+    /// in the long run
+    Mirror,
+}
+
+/// Built-in generators for calculating pixel colours
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum ColourGen {
+    /// Colours that move linearly through white, yellow, red and black
+    Test,
+    /// A randomly perturbed colour compared to previous colour
+    Rand,
+    /// A randomly perturbed hue, saturation and value compared to previous colour
+    Hsv,
+    /// A randomly rotated hue compared to previous colour, with bounded saturation and value
+    Hue,
+    /// A fade between two colours according to distance from the start along the tree
+    Gradient,
+    /// A random walk where red follows north-south and green follows east-west movement
+    Directional,
+    /// A hue picked by the direction each pixel was reached from, with some randomness
+    DirectionHue,
+    /// A randomly perturbed colour compared to previous colour, snapped to the nearest colour in a palette
+    Palette,
+    /// The palette generator, with a palette sampled from an image
+    Sampled,
+    /// A randomly perturbed brightness compared to previous colour, in shades of grey
+    #[clap(alias = "gray")]
+    Grey,
+    /// A randomly perturbed colour compared to previous colour, darkening with distance from the start point
+    Radial,
+    /// Each channel read from fractal noise, moving along it with distance from the start
+    Noise,
+    /// The rand and test generators mixed together, in proportion to the blend factor
+    Blend,
+}
+
+/// Built-in generators for calculating adjacencies for pixels
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum TreeGen {
+    /// A horizontal connection through the middle of the image, vertical connections down every column
+    Test,
+    /// Every pixel connected to two other pixels in a square spiral
+    Spiral,
+    /// Uses Prim's Algorithm to connect all pixels randomly into a tree
+    Prim,
+    /// Uses Wilson's Algorithm to connect all pixels into a uniformly random tree
+    Wilson,
+    /// Uses a randomised depth-first search to connect all pixels into long corridors
+    Dfs,
+    /// Uses Kruskal's Algorithm with random edge weights to connect all pixels into a tree
+    Kruskal,
+    /// Uses a breadth-first search to connect all pixels into a tree of concentric rings
+    Bfs,
+    /// Recursively splits the image into quadrants joined by single edges, for nested rectangles
+    Quad,
+    /// Every pixel connected to the next along a space-filling Hilbert curve, for blobby regions
+    Hilbert,
+}
+
+/// How Prim's Algorithm weights the edges out of a pixel, which shapes the
+/// branches it grows
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum WeightMode {
+    /// Diagonal edges favoured more the further from the top left corner,
+    /// orthogonal edges by one coordinate each
+    #[clap(alias = "coord-bias")]
+    DiagonalDrift,
+    /// Every edge equally likely
+    Uniform,
+    /// Edges leading away from the first start point favoured more the further
+    /// from it
+    Radial,
+    /// East and west edges favoured, for long horizontal runs
+    Horizontal,
+    /// North and south edges favoured, for long vertical runs
+    Vertical,
+    /// Diagonal edges favoured, for long slanting runs
+    Diagonal,
+}
+
+/// Every setting for generating an image, built up from defaults
+///
+/// The defaults match those of the command line tool.
+///
+/// ```no_run
+/// use lapidary::{generate, ColourGen, GeneratorConfig, TreeGen};
+///
+/// let config = GeneratorConfig::new(640, 480)
+///     .seed(3)
+///     .tree_gen(TreeGen::Prim)
+///     .colour_gen(ColourGen::Rand)
+///     .step_size(12)
+///     .start(0.5, 0.5)
+///     .with_progress(false);
+/// let image = generate(&config)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) colour_gen: ColourGen,
+    pub(crate) tree_gen: TreeGen,
+    pub(crate) seed: Option<u64>,
+    pub(crate) tree_seed: Option<u64>,
+    pub(crate) colour_seed: Option<u64>,
+    pub(crate) start_colour: StartColour,
+    pub(crate) start_image: Option<PathBuf>,
+    pub(crate) alpha: bool,
+    pub(crate) start_alpha: u8,
+    pub(crate) depth: Depth,
+    pub(crate) greyscale_output: bool,
+    pub(crate) connectivity: Connectivity,
+    pub(crate) weight_mode: WeightMode,
+    pub(crate) quad_min_size: usize,
+    pub(crate) tile: bool,
+    pub(crate) supersample: u32,
+    pub(crate) symmetry: Symmetry,
+    pub(crate) step_size: u16,
+    pub(crate) channel_steps: [Option<u16>; 3],
+    pub(crate) wrap_mode: WrapMode,
+    pub(crate) hue_step: f32,
+    pub(crate) sat_step: f32,
+    pub(crate) val_step: f32,
+    pub(crate) gradient_from: Rgb<u8>,
+    pub(crate) gradient_to: Rgb<u8>,
+    pub(crate) gradient_length: u32,
+    pub(crate) gradient_bounce: bool,
+    pub(crate) min_saturation: f32,
+    pub(crate) max_saturation: f32,
+    pub(crate) min_value: f32,
+    pub(crate) max_value: f32,
+    pub(crate) dir_saturation: f32,
+    pub(crate) dir_value: f32,
+    pub(crate) dir_blend: f32,
+    pub(crate) blend_factor: f32,
+    pub(crate) noise_scale: f64,
+    pub(crate) noise_octaves: u32,
+    pub(crate) noise_persistence: f64,
+    pub(crate) palette: Vec<Rgb<u8>>,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) starts: Vec<(f64, f64)>,
+    pub(crate) split_at: usize,
+    pub(crate) max_fanout: usize,
+    pub(crate) save_tree: Option<PathBuf>,
+    pub(crate) save_maze: Option<PathBuf>,
+    pub(crate) load_tree: Option<PathBuf>,
+    pub(crate) mask: Option<PathBuf>,
+    pub(crate) mask_threshold: u8,
+    pub(crate) mask_background: Rgb<u8>,
+    pub(crate) animate: Option<PathBuf>,
+    pub(crate) frame_every: Option<u32>,
+    pub(crate) animation_frames: u32,
+    pub(crate) animation_fps: u32,
+    pub(crate) progress: ProgressOutput,
+    pub(crate) verbosity: Verbosity,
+    pub(crate) print_stats: bool,
+    pub(crate) interrupt: Option<Interrupt>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            width: 1000,
+            height: 1000,
+            colour_gen: ColourGen::Test,
+            tree_gen: TreeGen::Test,
+            seed: None,
+            tree_seed: None,
+            colour_seed: None,
+            start_colour: StartColour::Fixed(Rgb([0, 0, 0])),
+            start_image: None,
+            alpha: false,
+            start_alpha: 255,
+            depth: Depth::Eight,
+            greyscale_output: false,
+            connectivity: Connectivity::Eight,
+            weight_mode: WeightMode::DiagonalDrift,
+            quad_min_size: 4,
+            tile: false,
+            supersample: 1,
+            symmetry: Symmetry::None,
+            step_size: 10,
+            channel_steps: [None; 3],
+            wrap_mode: WrapMode::Mirror,
+            hue_step: 10.,
+            sat_step: 0.05,
+            val_step: 0.05,
+            gradient_from: Rgb([0, 0, 0]),
+            gradient_to: Rgb([255, 255, 255]),
+            gradient_length: 1000,
+            gradient_bounce: false,
+            min_saturation: 0.6,
+            max_saturation: 1.,
+            min_value: 0.6,
+            max_value: 1.,
+            dir_saturation: 0.8,
+            dir_value: 0.9,
+            dir_blend: 0.8,
+            blend_factor: 0.5,
+            noise_scale: 0.02,
+            noise_octaves: 6,
+            noise_persistence: 0.5,
+            palette: Vec::new(),
+            x: 0.,
+            y: 0.,
+            starts: Vec::new(),
+            split_at: 256,
+            max_fanout: 8,
+            save_tree: None,
+            save_maze: None,
+            load_tree: None,
+            mask: None,
+            mask_threshold: 128,
+            mask_background: Rgb([0, 0, 0]),
+            animate: None,
+            frame_every: None,
+            animation_frames: 100,
+            animation_fps: 30,
+            progress: ProgressOutput::Stderr,
+            verbosity: Verbosity::silent(),
+            print_stats: false,
+            interrupt: None,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    /// Default settings for an image of the given size
+    pub fn new(width: u32, height: u32) -> Self {
+        GeneratorConfig {
+            width,
+            height,
+            ..GeneratorConfig::default()
+        }
+    }
+
+    /// Image width in pixels
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Image height in pixels
+    pub fn height(mut self, height: u32) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Which built-in generator to use for calculating pixel colours
+    pub fn colour_gen(mut self, colour_gen: ColourGen) -> Self {
+        self.colour_gen = colour_gen;
+        self
+    }
+
+    /// Which built-in generator to use for calculating adjacencies for pixels
+    pub fn tree_gen(mut self, tree_gen: TreeGen) -> Self {
+        self.tree_gen = tree_gen;
+        self
+    }
+
+    /// Seed for the random number generator, for reproducible images
+    ///
+    /// Used for both the tree and the colours, unless they have their own
+    /// seeds. Without a seed, one is picked from system entropy and printed
+    /// on stderr, unless progress is hidden.
+    pub fn seed(mut self, seed: impl Into<Option<u64>>) -> Self {
+        self.seed = seed.into();
+        self
+    }
+
+    /// Seed for growing the tree only, overriding [`GeneratorConfig::seed`]
+    ///
+    /// Keeping the tree seed while changing the main seed re-rolls the colours
+    /// of the same shape.
+    pub fn tree_seed(mut self, seed: impl Into<Option<u64>>) -> Self {
+        self.tree_seed = seed.into();
+        self
+    }
+
+    /// Seed for picking colours only, including a random start colour,
+    /// overriding [`GeneratorConfig::seed`]
+    pub fn colour_seed(mut self, seed: impl Into<Option<u64>>) -> Self {
+        self.colour_seed = seed.into();
+        self
+    }
+
+    /// Colour of the first pixel
+    pub fn start_colour(mut self, start_colour: StartColour) -> Self {
+        self.start_colour = start_colour;
+        self
+    }
+
+    /// Start each tree with the colour of the same pixel in a reference image,
+    /// instead of the start colour
+    ///
+    /// The reference image is scaled to the size of the image if it doesn't
+    /// match, with a warning.
+    pub fn start_image(mut self, path: impl Into<PathBuf>) -> Self {
+        self.start_image = Some(path.into());
+        self
+    }
+
+    /// Give the image an alpha channel, with the given alpha at the first pixel
+    pub fn alpha(mut self, start_alpha: impl Into<Option<u8>>) -> Self {
+        let start_alpha = start_alpha.into();
+        self.alpha = start_alpha.is_some();
+        self.start_alpha = start_alpha.unwrap_or(u8::MAX);
+        self
+    }
+
+    /// Number of bits per colour channel
+    pub fn depth(mut self, depth: Depth) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Store a single grey channel instead of red, green and blue
+    ///
+    /// Colours are converted to their luminance after generation, so this
+    /// suits the grey colour generator best.
+    pub fn greyscale_output(mut self, greyscale_output: bool) -> Self {
+        self.greyscale_output = greyscale_output;
+        self
+    }
+
+    /// Which neighbours of a pixel it can be joined to
+    pub fn connectivity(mut self, connectivity: Connectivity) -> Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    /// Generate the image this many times larger in each dimension, then scale
+    /// it down, to smooth out jagged edges
+    ///
+    /// Memory use grows with the square of the factor. A factor of 1 turns
+    /// supersampling off.
+    pub fn supersample(mut self, factor: u32) -> Self {
+        self.supersample = factor;
+        self
+    }
+
+    /// Only generate the top left half or quarter of the image, then mirror
+    /// it into the rest
+    ///
+    /// The middle column or row of an odd width or height is shared by both
+    /// halves. Start points, masks, loaded and saved trees and mazes all
+    /// cover just the generated part.
+    pub fn symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    /// How the Prim tree generator weights the edges out of each pixel
+    pub fn weight_mode(mut self, weight_mode: WeightMode) -> Self {
+        self.weight_mode = weight_mode;
+        self
+    }
+
+    /// Smallest quadrant size along either axis for the quad tree generator,
+    /// below which quadrants are filled with a linear scan
+    pub fn quad_min_size(mut self, quad_min_size: usize) -> Self {
+        self.quad_min_size = quad_min_size;
+        self
+    }
+
+    /// Join opposite edges of the image so that it tiles seamlessly
+    pub fn tile(mut self, tile: bool) -> Self {
+        self.tile = tile;
+        self
+    }
+
+    /// Maximum displacement of a colour channel for the random colour
+    /// generators
+    pub fn step_size(mut self, step_size: u16) -> Self {
+        self.step_size = step_size;
+        self
+    }
+
+    /// Maximum displacement of the red, green and blue channels separately for
+    /// the rand colour generator, overriding [`GeneratorConfig::step_size`]
+    pub fn channel_steps(
+        mut self,
+        red: Option<u16>,
+        green: Option<u16>,
+        blue: Option<u16>,
+    ) -> Self {
+        self.channel_steps = [red, green, blue];
+        self
+    }
+
+    /// What the random colour generator does when a channel runs past the end
+    /// of its range
+    pub fn wrap_mode(mut self, wrap_mode: WrapMode) -> Self {
+        self.wrap_mode = wrap_mode;
+        self
+    }
+
+    /// Maximum change in hue in degrees, and in saturation and value out of 1,
+    /// for the HSV colour generator
+    ///
+    /// The hue step is also used by the hue colour generator.
+    pub fn hsv_steps(mut self, hue_step: f32, sat_step: f32, val_step: f32) -> Self {
+        self.hue_step = hue_step;
+        self.sat_step = sat_step;
+        self.val_step = val_step;
+        self
+    }
+
+    /// Colours to fade between, and the number of pixels along the tree to fade
+    /// over, for the gradient colour generator
+    pub fn gradient(mut self, from: Rgb<u8>, to: Rgb<u8>, length: u32) -> Self {
+        self.gradient_from = from;
+        self.gradient_to = to;
+        self.gradient_length = length;
+        self
+    }
+
+    /// Fade back towards the root colour after the end of the gradient
+    pub fn gradient_bounce(mut self, bounce: bool) -> Self {
+        self.gradient_bounce = bounce;
+        self
+    }
+
+    /// Bounds on saturation, out of 1, for the hue colour generator
+    pub fn saturation_bounds(mut self, min: f32, max: f32) -> Self {
+        self.min_saturation = min;
+        self.max_saturation = max;
+        self
+    }
+
+    /// Bounds on value, out of 1, for the hue colour generator
+    pub fn value_bounds(mut self, min: f32, max: f32) -> Self {
+        self.min_value = min;
+        self.max_value = max;
+        self
+    }
+
+    /// Saturation, value and how closely hue follows direction, all out of 1,
+    /// for the direction-hue colour generator
+    pub fn direction_hue(mut self, saturation: f32, value: f32, blend: f32) -> Self {
+        self.dir_saturation = saturation;
+        self.dir_value = value;
+        self.dir_blend = blend;
+        self
+    }
+
+    /// How much of the test generator to mix into the rand generator, from 0
+    /// for none to 1 for only the test generator, for the blend colour
+    /// generator
+    pub fn blend_factor(mut self, blend_factor: f32) -> Self {
+        self.blend_factor = blend_factor;
+        self
+    }
+
+    /// Distance moved through the noise per step, number of octaves, and how
+    /// much each octave is scaled down from the last, for the noise colour
+    /// generator
+    pub fn noise(mut self, scale: f64, octaves: u32, persistence: f64) -> Self {
+        self.noise_scale = scale;
+        self.noise_octaves = octaves;
+        self.noise_persistence = persistence;
+        self
+    }
+
+    /// Colours for the palette colour generator to pick from
+    ///
+    /// Repeated colours are only kept once. At least two distinct colours are
+    /// needed.
+    pub fn palette(mut self, colours: impl IntoIterator<Item = Rgb<u8>>) -> Self {
+        self.palette.clear();
+        for colour in colours {
+            if !self.palette.contains(&colour) {
+                self.palette.push(colour);
+            }
+        }
+        self
+    }
+
+    /// Point to start the tree at, as coords in 0..=1
+    ///
+    /// `x` picks the column as a fraction of the width, and `y` the row as a
+    /// fraction of the height.
+    pub fn start(mut self, x: f64, y: f64) -> Self {
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    /// Extra point to start a tree at, as coords in 0..=1
+    ///
+    /// Only the Prim, Wilson and BFS tree generators grow a tree from each
+    /// start point.
+    pub fn extra_start(mut self, x: f64, y: f64) -> Self {
+        self.starts.push((x, y));
+        self
+    }
+
+    /// Number of branches waiting in a task before half are shared with a new
+    /// parallel task
+    pub fn split_at(mut self, split_at: usize) -> Self {
+        self.split_at = split_at;
+        self
+    }
+
+    /// Most children of one pixel that can be shared with other parallel
+    /// tasks, with the rest coloured by the task that reached the pixel
+    pub fn max_fanout(mut self, max_fanout: usize) -> Self {
+        self.max_fanout = max_fanout;
+        self
+    }
+
+    /// Write the finished tree to a file, so it can be loaded again with
+    /// [`GeneratorConfig::load_tree`]
+    pub fn save_tree(mut self, path: impl Into<PathBuf>) -> Self {
+        self.save_tree = Some(path.into());
+        self
+    }
+
+    /// Also draw the finished tree as a maze with [`render_maze`](crate::render_maze),
+    /// saved in the format picked by the file's extension
+    ///
+    /// Supersampled images have their maze drawn at the larger size.
+    pub fn save_maze(mut self, path: impl Into<PathBuf>) -> Self {
+        self.save_maze = Some(path.into());
+        self
+    }
+
+    /// Colour a tree saved by [`GeneratorConfig::save_tree`] instead of
+    /// growing a new one
+    ///
+    /// The image must have the same dimensions as when the tree was saved, and
+    /// the start points should be the same too, since each tree in a forest is
+    /// only coloured if it has a start point.
+    pub fn load_tree(mut self, path: impl Into<PathBuf>) -> Self {
+        self.load_tree = Some(path.into());
+        self
+    }
+
+    /// Only fill the pixels of a mask image at least as bright as the mask
+    /// threshold
+    ///
+    /// The mask must have the same dimensions as the image, and every start
+    /// point must be inside it. Other pixels are left the mask background
+    /// colour, as are kept pixels cut off from every start point.
+    pub fn mask(mut self, path: impl Into<PathBuf>) -> Self {
+        self.mask = Some(path.into());
+        self
+    }
+
+    /// Brightness from 0 to 255 that a mask pixel needs to be kept
+    pub fn mask_threshold(mut self, mask_threshold: u8) -> Self {
+        self.mask_threshold = mask_threshold;
+        self
+    }
+
+    /// Colour of the pixels a mask leaves out
+    pub fn mask_background(mut self, mask_background: Rgb<u8>) -> Self {
+        self.mask_background = mask_background;
+        self
+    }
+
+    /// Also write an animation of the fill, with a frame after every
+    /// `frame_every` pixels
+    ///
+    /// Paths ending in `.png` get an animated PNG, and anything else a GIF.
+    /// Without `frame_every`, the fill is split evenly across
+    /// `animation_frames` frames.
+    pub fn animate(mut self, path: impl Into<PathBuf>, frame_every: Option<u32>) -> Self {
+        self.animate = Some(path.into());
+        self.frame_every = frame_every;
+        self
+    }
+
+    /// Roughly how many frames an animation has, unless `frame_every` is set
+    pub fn animation_frames(mut self, animation_frames: u32) -> Self {
+        self.animation_frames = animation_frames;
+        self
+    }
+
+    /// Frames per second of an animation
+    pub fn animation_fps(mut self, animation_fps: u32) -> Self {
+        self.animation_fps = animation_fps;
+        self
+    }
+
+    /// Whether to draw progress bars on stderr
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = if progress {
+            ProgressOutput::Stderr
+        } else {
+            ProgressOutput::Hidden
+        };
+        self
+    }
+
+    /// Where to draw progress bars, overriding
+    /// [`with_progress`](GeneratorConfig::with_progress)
+    pub fn progress_output(mut self, output: ProgressOutput) -> Self {
+        self.progress = output;
+        self
+    }
+
+    /// Whether to log each stage of generation on stderr
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Whether to print the tree's degree statistics as JSON on stderr once
+    /// it's generated
+    pub fn print_stats(mut self, print_stats: bool) -> Self {
+        self.print_stats = print_stats;
+        self
+    }
+
+    /// Check an interrupt while placing pixels, and stop early once it's been
+    /// stopped
+    pub fn interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Step sizes for the red, green, blue and alpha channels, falling back on
+    /// the shared step size
+    pub(crate) fn step_sizes(&self) -> [u16; 4] {
+        let [r, g, b] = self
+            .channel_steps
+            .map(|step| step.unwrap_or(self.step_size));
+        [r, g, b, self.step_size]
+    }
+
+    /// Check the step sizes can be used with the colour generator and bit
+    /// depth
+    ///
+    /// Generating an image checks this too; calling it sooner lets a mistake
+    /// be reported before anything else is done.
+    pub fn check_step_sizes(&self) -> Result<()> {
+        if self
+            .step_sizes()
+            .iter()
+            .any(|&step| step > u16::from(u8::MAX))
+            && !(self.depth == Depth::Sixteen && self.colour_gen == ColourGen::Rand)
+        {
+            bail!(
+                "Step sizes above 255 are only supported by the rand colour generator with --depth 16"
+            );
+        }
+        Ok(())
+    }
+}
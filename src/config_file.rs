@@ -0,0 +1,471 @@
+use ::anyhow::{bail, ensure, Context, Result};
+use ::clap::{Arg, ArgMatches, Command, ValueSource};
+use ::std::{ffi::OsString, fmt::Write as _, fs, path::Path};
+
+/// Command line options read from a TOML file
+///
+/// Keys are the long names of the options, or the field names of options
+/// with only a short flag like `step_size` for `-D`, with either `_` or `-`
+/// between words. Only top-level `key = value` lines are supported, with strings,
+/// numbers, booleans and arrays of them as values.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Config {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    key: String,
+    line: usize,
+    value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    /// A number's text, passed to the option to parse
+    Number(String),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+/// Options that only make sense on the command line
+const COMMAND_LINE_ONLY: [&str; 4] = ["config", "dump-config", "help", "version"];
+
+impl Config {
+    /// Read a config file from disk
+    pub(crate) fn read(path: &Path) -> Result<Config> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        Config::parse(&text).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Config> {
+        let mut entries = Vec::<Entry>::new();
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let parsed = (|| {
+                ensure!(
+                    !trimmed.starts_with('['),
+                    "Tables aren't supported, only top-level keys"
+                );
+                let (key, rest) = trimmed.split_once('=').context("Expected `key = value`")?;
+                let key = key.trim().to_string();
+                ensure!(
+                    !key.is_empty()
+                        && key
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+                    "Expected `key = value`"
+                );
+                let mut chars = rest.chars().peekable();
+                skip_space(&mut chars);
+                let value = parse_value(&mut chars)?;
+                skip_space(&mut chars);
+                ensure!(
+                    chars.peek().is_none_or(|&c| c == '#'),
+                    "Unexpected text after value"
+                );
+                Ok((key, value))
+            })();
+            let (key, value) = parsed.with_context(|| format!("Line {}: `{}`", number, line))?;
+            if let Some(earlier) = entries.iter().find(|entry| entry.key == key) {
+                bail!(
+                    "Line {}: `{}` was already set on line {}",
+                    number,
+                    key,
+                    earlier.line
+                );
+            }
+            entries.push(Entry {
+                key,
+                line: number,
+                value,
+            });
+        }
+        Ok(Config { entries })
+    }
+
+    /// Command line arguments for each option in the file that wasn't given
+    /// on the command line already
+    ///
+    /// Each option's arguments are returned separately along with a
+    /// description of where they came from, so errors can be traced back to
+    /// the line they came from.
+    pub(crate) fn args(
+        &self,
+        command: &Command,
+        matches: &ArgMatches,
+    ) -> Result<Vec<(String, Vec<OsString>)>> {
+        let mut all_args = Vec::new();
+        for entry in &self.entries {
+            let source = format!("Line {}: `{}`", entry.line, entry.key);
+            let long = entry.key.replace('_', "-");
+            let arg = command
+                .get_arguments()
+                .find(|arg| {
+                    arg.get_id() == long
+                        || arg.get_long() == Some(&long)
+                        || arg
+                            .get_all_aliases()
+                            .is_some_and(|aliases| aliases.contains(&long.as_str()))
+                })
+                .with_context(|| format!("{} isn't an option", source))?;
+            let id = arg.get_id();
+            ensure!(
+                !COMMAND_LINE_ONLY.contains(&id) && !arg.is_positional(),
+                "{} can only be given on the command line",
+                source
+            );
+            if matches.value_source(id) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            let flag = match (arg.get_long(), arg.get_short()) {
+                (Some(long), _) => format!("--{}=", long),
+                (None, Some(short)) => format!("-{}", short),
+                (None, None) => bail!("{} can only be given on the command line", source),
+            };
+            let args = option_args(arg, &flag, &entry.value).context(source.clone())?;
+            all_args.push((source, args));
+        }
+        Ok(all_args)
+    }
+}
+
+/// The arguments that set an option to a value
+///
+/// `flag` is `--long=` or `-s`, which the value is appended to.
+fn option_args(arg: &Arg, flag: &str, value: &Value) -> Result<Vec<OsString>> {
+    if !arg.is_takes_value_set() {
+        return match value {
+            Value::Boolean(true) => Ok(vec![flag.trim_end_matches('=').into()]),
+            Value::Boolean(false) => Ok(Vec::new()),
+            _ => bail!("Expected `true` or `false`"),
+        };
+    }
+    let text = |value: &Value| match value {
+        Value::String(s) | Value::Number(s) => Ok(format!("{}{}", flag, s).into()),
+        Value::Boolean(b) => Ok(format!("{}{}", flag, b).into()),
+        Value::Array(_) => bail!("Arrays can't be nested"),
+    };
+    match value {
+        Value::Array(values) => {
+            ensure!(
+                arg.is_multiple_occurrences_set(),
+                "Expected a single value, not an array"
+            );
+            values.iter().map(text).collect()
+        }
+        value => Ok(vec![text(value)?]),
+    }
+}
+
+/// The options given on the command line or by a config file, as TOML that
+/// `--config` can read back
+///
+/// Options left at their defaults are skipped, so a preset only pins down the
+/// choices that were made.
+pub(crate) fn dump(command: &Command, matches: &ArgMatches) -> String {
+    let mut toml = String::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id();
+        if COMMAND_LINE_ONLY.contains(&id)
+            || arg.is_positional()
+            || matches.value_source(id) != Some(ValueSource::CommandLine)
+        {
+            continue;
+        }
+        let key = arg.get_long().unwrap_or(id).replace('-', "_");
+        if !arg.is_takes_value_set() {
+            let _ = writeln!(toml, "{} = true", key);
+            continue;
+        }
+        let values = matches
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|value| toml_value(&value.to_string_lossy()))
+            .collect::<Vec<_>>();
+        if arg.is_multiple_occurrences_set() {
+            let _ = writeln!(toml, "{} = [{}]", key, values.join(", "));
+        } else if let Some(value) = values.first() {
+            let _ = writeln!(toml, "{} = {}", key, value);
+        }
+    }
+    toml
+}
+
+/// A bare number if the text is one, otherwise a quoted string
+fn toml_value(text: &str) -> String {
+    let number = text.parse::<i64>().is_ok()
+        || (text.parse::<f64>().is_ok_and(f64::is_finite)
+            && text
+                .chars()
+                .all(|c| c.is_ascii_digit() || "+-.eE".contains(c)));
+    if number {
+        return text.to_string();
+    }
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04X}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+type Chars<'a> = ::std::iter::Peekable<::std::str::Chars<'a>>;
+
+fn skip_space(chars: &mut Chars) {
+    while chars.peek().is_some_and(|&c| c == ' ' || c == '\t') {
+        chars.next();
+    }
+}
+
+fn parse_value(chars: &mut Chars) -> Result<Value> {
+    match chars.peek() {
+        Some('"') => parse_basic_string(chars).map(Value::String),
+        Some('\'') => parse_literal_string(chars).map(Value::String),
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            loop {
+                skip_space(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    break;
+                }
+                values.push(parse_value(chars)?);
+                skip_space(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => break,
+                    _ => bail!("Expected `,` or `]` in array"),
+                }
+            }
+            Ok(Value::Array(values))
+        }
+        _ => {
+            let mut bare = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || "+-._".contains(c)) {
+                    break;
+                }
+                bare.push(c);
+                chars.next();
+            }
+            match bare.as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                _ => {
+                    let number = bare.replace('_', "");
+                    ensure!(
+                        number.parse::<i64>().is_ok() || number.parse::<f64>().is_ok(),
+                        "Expected a string, number, boolean or array; strings need quotes"
+                    );
+                    Ok(Value::Number(number))
+                }
+            }
+        }
+    }
+}
+
+/// A single-quoted string, which has no escapes
+fn parse_literal_string(chars: &mut Chars) -> Result<String> {
+    ensure!(chars.next() == Some('\''), "Expected a string");
+    let mut out = String::new();
+    loop {
+        match chars.next().context("Unterminated string")? {
+            '\'' => return Ok(out),
+            c => out.push(c),
+        }
+    }
+}
+
+fn parse_basic_string(chars: &mut Chars) -> Result<String> {
+    ensure!(chars.next() == Some('"'), "Expected a string");
+    let mut out = String::new();
+    loop {
+        match chars.next().context("Unterminated string")? {
+            '"' => return Ok(out),
+            '\\' => match chars.next().context("Unterminated string")? {
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                escape @ ('u' | 'U') => {
+                    let len = if escape == 'u' { 4 } else { 8 };
+                    let hex = chars.by_ref().take(len).collect::<String>();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .context("Invalid unicode escape in string")?;
+                    out.push(code);
+                }
+                c => bail!("Invalid escape `\\{}` in string", c),
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+    use ::clap::{CommandFactory, FromArgMatches};
+
+    /// The config file's arguments, given these command line arguments
+    fn args(text: &str, command_line: &[&str]) -> Result<Vec<(String, Vec<OsString>)>> {
+        let command = Cli::command();
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(["lapidary"].iter().chain(command_line))
+            .unwrap();
+        Config::parse(text)?.args(&command, &matches)
+    }
+
+    #[test]
+    fn misspelt_keys_are_reported_with_their_line() {
+        let error = args("width = 10\n\nhieght = 20\n", &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Line 3: `hieght` isn't an option");
+    }
+
+    #[test]
+    fn keys_can_only_be_set_once() {
+        let error = Config::parse("width = 10\n# again\nwidth = 20\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 3: `width` was already set on line 1"
+        );
+    }
+
+    #[test]
+    fn tables_are_rejected() {
+        let error = Config::parse("width = 10\n[colours]\nstep_size = 3\n").unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "Line 2: `[colours]`: Tables aren't supported, only top-level keys"
+        );
+    }
+
+    #[test]
+    fn arrays_only_fit_options_given_more_than_once() {
+        let error = args("width = [10, 20]", &[]).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "Line 1: `width`: Expected a single value, not an array"
+        );
+        let starts = args("start = [\"0.25,0.5\", \"0.75,0.5\"]", &[]).unwrap();
+        assert_eq!(
+            starts[0].1,
+            [
+                OsString::from("--start=0.25,0.5"),
+                "--start=0.75,0.5".into()
+            ]
+        );
+    }
+
+    #[test]
+    fn basic_strings_unescape() {
+        let config = Config::parse(r#"palette_file = "a\"b\\c\td\u00e9\U0001F48E""#).unwrap();
+        assert_eq!(
+            config.entries[0].value,
+            Value::String("a\"b\\c\td\u{e9}\u{1F48E}".into())
+        );
+        for bad in [r#""\q""#, r#""\u00""#, r#""abc"#, r#""abc\""#] {
+            let text = format!("palette_file = {}", bad);
+            assert!(Config::parse(&text).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn command_line_options_beat_the_file() {
+        let args = args("width = 10\nheight = 20\n", &["-W", "30", "out.png"]).unwrap();
+        assert_eq!(
+            args,
+            [("Line 2: `height`".into(), vec!["--height=20".into()])]
+        );
+    }
+
+    #[test]
+    fn dumped_options_read_back_the_same() {
+        let command = Cli::command();
+        let command_line = [
+            "lapidary",
+            "-W",
+            "50",
+            "-S",
+            "18446744073709551615",
+            "-C",
+            "palette",
+            "--palette-file",
+            "my \"sea\"\\\tcolours.txt",
+            "--start",
+            "0.25,0.5",
+            "--start",
+            "0.75,0.5",
+            "--tile",
+            "--dump-config",
+            "out.png",
+        ];
+        let matches = command.clone().try_get_matches_from(command_line).unwrap();
+        let toml = dump(&command, &matches);
+        let empty = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(["lapidary"])
+            .unwrap();
+        let config_args = Config::parse(&toml)
+            .unwrap()
+            .args(&command, &empty)
+            .unwrap();
+        let read_back = command
+            .clone()
+            .try_get_matches_from(
+                ["lapidary".into()]
+                    .into_iter()
+                    .chain(config_args.into_iter().flat_map(|(_, args)| args))
+                    .chain(["--dump-config".into(), "out.png".into()]),
+            )
+            .unwrap();
+        assert_eq!(dump(&command, &read_back), toml);
+        let cli = Cli::from_arg_matches(&read_back).unwrap();
+        assert_eq!(cli.seed, Some(u64::MAX));
+        assert_eq!(
+            cli.palette_file.as_deref(),
+            Some(Path::new("my \"sea\"\\\tcolours.txt"))
+        );
+        assert_eq!(cli.starts, [(0.25, 0.5), (0.75, 0.5)]);
+    }
+
+    #[test]
+    fn literal_strings_keep_backslashes_and_must_be_closed() {
+        let config = Config::parse("palette = 'C:\\palettes\\sea.txt'").unwrap();
+        assert_eq!(
+            config.entries[0].value,
+            Value::String("C:\\palettes\\sea.txt".into())
+        );
+        let error = Config::parse("palette = 'abc").unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "Line 1: `palette = 'abc`: Unterminated string"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use ::lapidary::Interrupt;
+use ::std::sync::OnceLock;
+
+/// The interrupt stopped by the first Ctrl-C
+static INTERRUPT: OnceLock<Interrupt> = OnceLock::new();
+
+/// Handle Ctrl-C by stopping `interrupt` if pixels are being placed, so the
+/// unfinished image can be saved
+///
+/// Ctrl-C at any other time, or a second Ctrl-C, quits straight away. Only
+/// Unix signals are caught; elsewhere Ctrl-C always quits.
+pub(crate) fn catch(interrupt: &Interrupt) {
+    if INTERRUPT.set(interrupt.clone()).is_err() {
+        return;
+    }
+    #[cfg(unix)]
+    // SAFETY: the handler only touches atomics and calls async-signal-safe
+    // functions
+    unsafe {
+        ::libc::signal(
+            ::libc::SIGINT,
+            handle as extern "C" fn(_) as ::libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle(_signal: ::libc::c_int) {
+    // nothing here can allocate or take a lock, so messages are written
+    // straight to stderr
+    let write = |message: &str| {
+        // SAFETY: the message is valid for its whole length
+        unsafe {
+            ::libc::write(
+                ::libc::STDERR_FILENO,
+                message.as_ptr().cast(),
+                message.len(),
+            )
+        };
+    };
+    if INTERRUPT.get().is_some_and(Interrupt::stop) {
+        write("\nInterrupted, saving the pixels placed so far; Ctrl-C again to quit\n");
+        return;
+    }
+    write("\nInterrupted\n");
+    // SAFETY: exits without running anything else, which is what's wanted
+    unsafe { ::libc::_exit(130) };
+}
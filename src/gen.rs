@@ -1,155 +1,1260 @@
-use super::{Cli, ColourGen, TreeGen};
+use super::{
+    animation::{Animation, Pacing},
+    ColourGen, Connectivity, Depth, GeneratorConfig, Interrupt, ProgressOutput, StartColour,
+    Symmetry, TreeGen, Verbosity, WeightMode, WrapMode,
+};
 use ::anyhow::{bail, Context, Result};
-use ::image::{ImageBuffer, Pixel, Rgb};
-use ::indicatif::{ProgressBar, ProgressStyle};
-use ::rand::prelude::SeedableRng;
+use ::image::{
+    imageops::{self, FilterType},
+    DynamicImage, GrayImage, ImageBuffer, Luma, Pixel, Primitive, Rgb, RgbImage, Rgba,
+};
+use ::indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use ::rand::{
+    distributions::uniform::SampleUniform,
+    prelude::{Rng, SeedableRng},
+    rngs::OsRng,
+};
 use ::rand_xoshiro::Xoshiro128PlusPlus;
 use ::rayon::{scope, Scope};
 use ::std::{
+    borrow::Cow,
+    fmt::Display,
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
+    iter,
+    num::TryFromIntError,
+    path::Path,
     sync::{
-        mpsc::{channel, Sender},
-        Arc,
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
-use trees::Neighbours;
+use colour::AnyColour;
+pub use trees::{tree_statistics, Neighbours, TreeStats};
 
 mod colour;
+mod mask;
+mod tree_io;
 mod trees;
 
-pub(super) fn new_image(
-    Cli {
-        width,
-        height,
-        colour_gen,
-        tree_gen,
-        seed,
-        step_size,
-        x,
-        y,
-        ..
-    }: Cli,
-) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
-    // Progress bar template
-    let style = ProgressStyle::default_bar()
-        .progress_chars("## ")
-        .template("[{bar}] {prefix} - {percent}% done, {eta} left - {msg}");
-    // Image dimensions
-    let (usize_width, usize_height) = (
-        width
-            .try_into()
-            .context("Failed to convert width u32 to usize")?,
-        height
-            .try_into()
-            .context("Failed to convert height u32 to usize")?,
-    );
-    let (start_row, start_col): (usize, usize) = (
-        (x * f64::try_from(width).context("Couldn't convert dimensions to float")?) as usize,
-        (y * f64::try_from(height).context("Couldn't convert dimensions to float")?) as usize,
-    );
-    let start_u32 = (
-        start_row
-            .try_into()
-            .context("Couldn't convert start coordinates usize to u32")?,
-        start_col
-            .try_into()
-            .context("Couldn't convert start coordinates usize to u32")?,
-    );
-    // Random number seeding
-    let rng = match seed {
-        Some(seed) => Xoshiro128PlusPlus::seed_from_u64(seed),
-        None => Xoshiro128PlusPlus::from_entropy(),
+/// Generate an 8-bit RGB image
+///
+/// Images configured with an alpha channel or 16 bits per channel are
+/// converted down; use [`generate_dynamic`] to keep them as they are.
+pub fn generate(config: &GeneratorConfig) -> Result<RgbImage> {
+    Ok(generate_dynamic(config)?.into_rgb8())
+}
+
+/// Size of image buffer above which a warning is printed
+const LARGE_IMAGE_BYTES: u64 = 500_000_000;
+
+/// Generate an image whose pixel type depends on the config's alpha and depth
+pub fn generate_dynamic(config: &GeneratorConfig) -> Result<DynamicImage> {
+    Ok(generate_timed(config)?.0)
+}
+
+/// How long each phase of generating an image took
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Generating or loading the spanning tree
+    pub tree_gen: Duration,
+    /// Removing edges that lead off the image
+    pub prune: Duration,
+    /// Walking the tree to colour pixels, including scaling down supersampled
+    /// images, or drawing the tree as a maze
+    pub colour: Duration,
+}
+
+/// Generate an image like [`generate_dynamic`], along with how long each phase
+/// of generation took
+pub fn generate_timed(config: &GeneratorConfig) -> Result<(DynamicImage, Timings)> {
+    if config.supersample == 0 {
+        bail!("Supersampling factor must be at least 1");
+    }
+    if config.supersample > 1 {
+        let factor = config.supersample;
+        let large = GeneratorConfig {
+            width: config
+                .width
+                .checked_mul(factor)
+                .context("Supersampled width is too large")?,
+            height: config
+                .height
+                .checked_mul(factor)
+                .context("Supersampled height is too large")?,
+            supersample: 1,
+            ..config.clone()
+        };
+        let (image, mut timings) =
+            generate_timed(&large).context("Failed to generate supersampled image")?;
+        let start = Instant::now();
+        let image = image.resize_exact(config.width, config.height, FilterType::Lanczos3);
+        timings.colour += start.elapsed();
+        return Ok((image, timings));
+    }
+    config.check_step_sizes()?;
+    if matches!(config.colour_gen, ColourGen::Palette | ColourGen::Sampled)
+        && config.palette.len() < 2
+    {
+        bail!("Palette colour generator needs at least 2 distinct colours");
+    }
+    let mut timings = Timings::default();
+    let (plan, tree) = plan_tree(config, &mut timings)?;
+    if let Some(path) = &config.save_maze {
+        render_maze(plan.usize_width, plan.usize_height, &tree)?
+            .save(path)
+            .with_context(|| format!("Failed to save maze to {}", path.display()))?;
+        plan.progress
+            .message(format_args!("Saved maze to {}", path.display()));
+    }
+    let start = Instant::now();
+    let (colour_gen, start_colours) = plan.builtin_colour(config);
+    let roots = plan
+        .roots
+        .iter()
+        .copied()
+        .zip(start_colours)
+        .collect::<Vec<_>>();
+    let plotting = plan.plotting(config)?;
+    // Allocate image in memory and apply colour generator
+    let image = match (config.alpha, config.depth) {
+        (false, Depth::Eight) => DynamicImage::ImageRgb8(colour_image(
+            tree,
+            &roots,
+            Rgb([0; 3]),
+            colour_gen,
+            (plan.width, plan.height),
+            plotting,
+        )?),
+        (true, Depth::Eight) => DynamicImage::ImageRgba8(colour_image(
+            tree,
+            &roots,
+            Rgba([0; 4]).with_alpha(config.start_alpha),
+            colour_gen,
+            (plan.width, plan.height),
+            plotting,
+        )?),
+        (false, Depth::Sixteen) => DynamicImage::ImageRgb16(colour_image(
+            tree,
+            &roots,
+            Rgb([0; 3]),
+            colour_gen,
+            (plan.width, plan.height),
+            plotting,
+        )?),
+        (true, Depth::Sixteen) => DynamicImage::ImageRgba16(colour_image(
+            tree,
+            &roots,
+            Rgba([0; 4]).with_alpha(config.start_alpha),
+            colour_gen,
+            (plan.width, plan.height),
+            plotting,
+        )?),
+    };
+    plan.progress.message("Coloured pixels placed");
+    let image = if config.greyscale_output {
+        match image {
+            DynamicImage::ImageRgb8(_) => DynamicImage::ImageLuma8(image.to_luma8()),
+            DynamicImage::ImageRgba8(_) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+            DynamicImage::ImageRgb16(_) => DynamicImage::ImageLuma16(image.to_luma16()),
+            _ => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        }
+    } else {
+        image
+    };
+    timings.colour = start.elapsed();
+    Ok((image, timings))
+}
+
+/// Draw a config's tree as a maze, like [`render_maze`], without colouring it
+///
+/// Supersampling is ignored, so the maze is always of a tree the size of the
+/// image.
+pub fn generate_maze(config: &GeneratorConfig) -> Result<GrayImage> {
+    Ok(generate_maze_timed(config)?.0)
+}
+
+/// Draw a config's tree as a maze like [`generate_maze`], along with how long
+/// each phase took
+pub fn generate_maze_timed(config: &GeneratorConfig) -> Result<(GrayImage, Timings)> {
+    let config = GeneratorConfig {
+        supersample: 1,
+        ..config.clone()
+    };
+    let mut timings = Timings::default();
+    let (plan, tree) = plan_tree(&config, &mut timings)?;
+    let start = Instant::now();
+    let maze = render_maze(plan.usize_width, plan.usize_height, &tree)?;
+    timings.colour = start.elapsed();
+    Ok((maze, timings))
+}
+
+/// Check a config, then build or load its tree and trim it to the image
+///
+/// Saves the tree or prints its statistics if the config asks for them.
+fn plan_tree(config: &GeneratorConfig, timings: &mut Timings) -> Result<(Plan, Vec<Neighbours>)> {
+    if config.tile
+        && config.load_tree.is_none()
+        && matches!(
+            config.tree_gen,
+            TreeGen::Test | TreeGen::Spiral | TreeGen::Quad | TreeGen::Hilbert
+        )
+    {
+        bail!(
+            "{:?} tree generator doesn't support tiling, since it can't join opposite edges",
+            config.tree_gen
+        );
+    }
+    let plan = Plan::new(config, Some(config.tree_gen))?;
+    let start = Instant::now();
+    let tree = match &config.load_tree {
+        Some(path) => {
+            let tree = tree_io::load(path, (plan.width, plan.height))?;
+            plan.progress.message("Loaded tree");
+            timings.tree_gen = start.elapsed();
+            let start = Instant::now();
+            let tree = plan.mask(tree)?;
+            timings.prune = start.elapsed();
+            tree
+        }
+        None => {
+            let tree = plan.builtin_tree(config)?;
+            timings.tree_gen = start.elapsed();
+            let start = Instant::now();
+            let tree = plan.mask(plan.prune(tree)?)?;
+            timings.prune = start.elapsed();
+            tree
+        }
     };
-    // Choose tree generator
-    let tree_gen = match tree_gen {
-        TreeGen::Test => colour::TestGen
-            .tree(usize_width, usize_height, style.clone())
-            .context("Failed to generate test tree for image")?,
-        TreeGen::Spiral => trees::SpiralTree
-            .tree(usize_width, usize_height, style.clone())
-            .context("Failed to generate spiral tree for image")?,
-        TreeGen::Prim => trees::PrimTree {
-            rng: rng.clone(),
-            initial_points: vec![(usize_width * start_col) + start_row],
-            weights: move |point| {
-                move |&v| {
-                    let (x_weight, y_weight) = (
-                        u64::try_from(point.0)
-                            .expect("Couldn't convert coordinate when weighting colours"),
-                        u64::try_from(point.1)
-                            .expect("Couldn't convert coordinate when weighting colours"),
+    if config.print_stats {
+        eprintln!("{}", tree_statistics(&tree).to_json());
+    }
+    if let Some(path) = &config.save_tree {
+        tree_io::save(path, (plan.width, plan.height), &tree)?;
+        plan.progress
+            .message(format_args!("Saved tree to {}", path.display()));
+    }
+    Ok((plan, tree))
+}
+
+/// Generate an 8-bit RGB image with custom tree and colour generators
+///
+/// The config's choice of built-in generators, alpha and depth are ignored,
+/// and the tree is coloured from the first start point only.
+pub fn generate_with<T: GenTree, G: GenColour<Rgb<u8>> + 'static>(
+    config: &GeneratorConfig,
+    mut tree_gen: T,
+    colour_gen: G,
+) -> Result<RgbImage> {
+    let plan = Plan::new(config, None)?;
+    let tree = tree_gen
+        .tree(plan.usize_width, plan.usize_height, plan.progress.clone())
+        .context("Failed to generate custom tree for image")?;
+    if tree.len() != plan.usize_width * plan.usize_height {
+        bail!(
+            "Custom tree has {} pixels, but the image has {}",
+            tree.len(),
+            plan.usize_width * plan.usize_height
+        );
+    }
+    let tree = plan.mask(plan.prune(tree)?)?;
+    let plotting = plan.plotting(config)?;
+    let roots = plan
+        .roots
+        .iter()
+        .copied()
+        .zip(plan.start_colours.iter().copied())
+        .collect::<Vec<_>>();
+    let image = colour_image(
+        tree,
+        &roots,
+        Rgb([0; 3]),
+        colour_gen,
+        (plan.width, plan.height),
+        plotting,
+    )?;
+    plan.progress.message("Coloured pixels placed");
+    Ok(image)
+}
+
+/// Settings worked out from a config that every generator shares
+struct Plan {
+    /// Dimensions of the part of the image that's generated, before it's
+    /// mirrored into the rest
+    width: u32,
+    height: u32,
+    usize_width: usize,
+    usize_height: usize,
+    topology: Topology,
+    /// Start points as indices into the image, for tree generators
+    initial_points: Vec<usize>,
+    /// Start points as (row, col), for colouring
+    roots: Vec<(u32, u32)>,
+    /// Whether each pixel is kept, if the image is masked
+    mask: Option<Vec<bool>>,
+    /// Random number generator for growing the tree
+    tree_rng: Xoshiro128PlusPlus,
+    /// Random number generator for picking colours
+    colour_rng: Xoshiro128PlusPlus,
+    /// Colour of the pixel at each start point
+    start_colours: Vec<Rgb<u8>>,
+    progress: Progress,
+}
+
+impl Plan {
+    /// Check a config and work out its start points and random number generators
+    ///
+    /// Only the given built-in tree generators can use more than one start
+    /// point; the rest are warned about and dropped.
+    fn new(config: &GeneratorConfig, tree_gen: Option<TreeGen>) -> Result<Plan> {
+        let &GeneratorConfig {
+            width,
+            height,
+            seed,
+            tree_seed,
+            colour_seed,
+            start_colour,
+            connectivity,
+            tile,
+            min_saturation,
+            max_saturation,
+            min_value,
+            max_value,
+            hue_step,
+            sat_step,
+            val_step,
+            dir_saturation,
+            dir_value,
+            dir_blend,
+            blend_factor,
+            x,
+            y,
+            split_at,
+            max_fanout,
+            verbosity,
+            ..
+        } = config;
+        let progress = Progress::to(&config.progress)?.with_verbosity(verbosity);
+        if width == 0 || height == 0 {
+            bail!("Images must be at least 1 pixel wide and tall");
+        }
+        if min_saturation > max_saturation {
+            bail!("Minimum saturation cannot be greater than maximum saturation");
+        }
+        if min_value > max_value {
+            bail!("Minimum value cannot be greater than maximum value");
+        }
+        if ![hue_step, sat_step, val_step]
+            .iter()
+            .all(|step| step.is_finite() && *step >= 0.)
+        {
+            bail!("HSV steps must be finite and cannot be negative");
+        }
+        if ![dir_saturation, dir_value, dir_blend]
+            .iter()
+            .all(|amount| (0. ..=1.).contains(amount))
+        {
+            bail!("Direction-hue saturation, value and blend must be between 0 and 1");
+        }
+        if !(0. ..=1.).contains(&blend_factor) {
+            bail!("Blend factor must be between 0 and 1");
+        }
+        if split_at == 0 {
+            bail!("Tasks must be allowed to hold at least 1 waiting branch");
+        }
+        if max_fanout == 0 {
+            bail!("Pixels must be allowed to share at least 1 child with other tasks");
+        }
+        for &(x, y) in iter::once(&(x, y)).chain(&config.starts) {
+            if !((0. ..=1.).contains(&x) && (0. ..=1.).contains(&y)) {
+                bail!("Start point {},{} is outside the image", x, y);
+            }
+        }
+        let channels = if config.alpha { 4 } else { 3 };
+        let channel_bytes = match config.depth {
+            Depth::Eight => 1,
+            Depth::Sixteen => 2,
+        };
+        let image_bytes = u64::from(width) * u64::from(height) * channels * channel_bytes;
+        if image_bytes > LARGE_IMAGE_BYTES {
+            progress.warn(format_args!(
+                "the image alone needs {} MB of memory",
+                image_bytes / 1_000_000
+            ));
+        }
+        // Only the top left of a symmetric image is generated, including the
+        // middle column or row of an odd width or height
+        let width = if config.symmetry.horizontal() {
+            width.div_ceil(2)
+        } else {
+            width
+        };
+        let height = if config.symmetry.vertical() {
+            height.div_ceil(2)
+        } else {
+            height
+        };
+        if tile && (width < 3 || height < 3) {
+            bail!("Tiled images must be at least 3 pixels wide and tall, not counting mirrored halves");
+        }
+        // Image dimensions
+        let (usize_width, usize_height) = (
+            width
+                .try_into()
+                .context("Failed to convert width u32 to usize")?,
+            height
+                .try_into()
+                .context("Failed to convert height u32 to usize")?,
+        );
+        // Start points as (row, col), beginning with -X/-Y, with duplicates removed
+        let mut start_points: Vec<(usize, usize)> = Vec::new();
+        for &(x, y) in iter::once(&(x, y)).chain(&config.starts) {
+            let point = (
+                fraction_to_pixel(y, usize_height),
+                fraction_to_pixel(x, usize_width),
+            );
+            if !start_points.contains(&point) {
+                start_points.push(point);
+            }
+        }
+        // Only some generators can grow a forest from several start points
+        let multiple_roots = matches!(
+            tree_gen,
+            Some(TreeGen::Prim | TreeGen::Wilson | TreeGen::Bfs)
+        );
+        if !multiple_roots && start_points.len() > 1 {
+            let name = match tree_gen {
+                Some(tree_gen) => format!("{:?}", tree_gen),
+                None => "Custom".to_string(),
+            };
+            progress.warn(format_args!(
+                "{} tree generator only supports one start point, so only the first will be used",
+                name
+            ));
+            start_points.truncate(1);
+        }
+        let initial_points = start_points
+            .iter()
+            .map(|&(row, col)| row * usize_width + col)
+            .collect::<Vec<_>>();
+        let roots = start_points
+            .iter()
+            .map(|&(row, col)| Ok((u32::try_from(row)?, u32::try_from(col)?)))
+            .collect::<Result<Vec<_>, TryFromIntError>>()
+            .context("Couldn't convert start coordinates usize to u32")?;
+        let mask = config
+            .mask
+            .as_ref()
+            .map(|path| mask::load(path, (width, height), config.mask_threshold))
+            .transpose()?;
+        if let Some(mask) = &mask {
+            for (&index, &(row, col)) in initial_points.iter().zip(&roots) {
+                if !mask[index] {
+                    bail!(
+                        "Start point at pixel {},{} is outside the mask; move it with -X/-Y",
+                        col,
+                        row
                     );
-                    1 + if (Neighbours::NORTH | Neighbours::SOUTH).contains(v) {
-                        y_weight * 2
-                    } else if (Neighbours::EAST | Neighbours::WEST).contains(v) {
-                        x_weight * 2
-                    } else {
-                        y_weight + x_weight
-                    }
                 }
-            },
+            }
         }
-        .tree(usize_width, usize_height, style.clone())
-        .context("Failed to generate Prim's Algorithm tree for image")?,
-    };
-    let mut tree = tree_gen;
-    eprintln!("Finished generating tree");
-    prune_edges(usize_width, usize_height, style.clone(), &mut tree)
-        .context("Failed to prune tree at edge of grid")?;
-    eprintln!("Finished pruning tree");
-    // Allocated image in memory
-    let buf = ImageBuffer::new(width, height);
-    eprintln!("Empty buffer allocated");
-    // Choose and apply colour generator
-    let buf = match colour_gen {
-        ColourGen::Test => lay_colours(
-            Arc::new(tree),
-            start_u32,
-            *Pixel::from_slice(&[0, 0, 0]),
-            colour::TestGen,
-            buf,
-            style,
-        ),
-        ColourGen::Rand => {
-            let rand = colour::RandColour { step_size, rng };
-            lay_colours(
-                Arc::new(tree),
-                start_u32,
-                *Pixel::from_slice(&[0, 0, 0]),
-                rand,
-                buf,
-                style,
+        // Random number seeding, where the tree and colours both fall back to
+        // the main seed, so they share a generator like before they could be
+        // seeded separately
+        let seed = match (seed, tree_seed, colour_seed) {
+            (Some(seed), _, _) => seed,
+            // not needed by either stage
+            (None, Some(_), Some(_)) => 0,
+            (None, _, _) => {
+                let seed = OsRng.gen();
+                // printed whenever anything is, so a lucky image can be made
+                // again, and untimestamped, so it can be picked out and
+                // passed to `-S`
+                if config.progress != ProgressOutput::Hidden {
+                    progress.println(format_args!("seed: {}", seed));
+                }
+                seed
+            }
+        };
+        let tree_rng = Xoshiro128PlusPlus::seed_from_u64(tree_seed.unwrap_or(seed));
+        let colour_rng = Xoshiro128PlusPlus::seed_from_u64(colour_seed.unwrap_or(seed));
+        let start_colours = match &config.start_image {
+            Some(path) => {
+                let reference = load_start_image(path, (width, height), &progress)?;
+                roots
+                    .iter()
+                    .map(|&(row, col)| *reference.get_pixel(col, row))
+                    .collect()
+            }
+            None => {
+                let start_colour = match start_colour {
+                    StartColour::Fixed(colour) => colour,
+                    StartColour::Random => {
+                        // use a separate stream so the rest of the image is
+                        // unaffected
+                        let mut start_rng = colour_rng.clone();
+                        start_rng.long_jump();
+                        Rgb(start_rng.gen())
+                    }
+                };
+                vec![start_colour; roots.len()]
+            }
+        };
+        Ok(Plan {
+            width,
+            height,
+            usize_width,
+            usize_height,
+            topology: Topology { connectivity, tile },
+            initial_points,
+            roots,
+            mask,
+            tree_rng,
+            colour_rng,
+            start_colours,
+            progress,
+        })
+    }
+
+    /// Grow a tree with the config's built-in tree generator
+    fn builtin_tree(&self, config: &GeneratorConfig) -> Result<Vec<Neighbours>> {
+        let tree = match config.tree_gen {
+            TreeGen::Test => colour::TestGen
+                .tree(self.usize_width, self.usize_height, self.progress.clone())
+                .context("Failed to generate test tree for image")?,
+            TreeGen::Spiral => trees::SpiralTree
+                .tree(self.usize_width, self.usize_height, self.progress.clone())
+                .context("Failed to generate spiral tree for image")?,
+            TreeGen::Prim => trees::PrimTree {
+                rng: self.tree_rng.clone(),
+                initial_points: self.initial_points.clone(),
+                topology: self.topology,
+                weights: config.weight_mode,
+            }
+            .tree(self.usize_width, self.usize_height, self.progress.clone())
+            .context("Failed to generate Prim's Algorithm tree for image")?,
+            TreeGen::Wilson => trees::WilsonTree {
+                rng: self.tree_rng.clone(),
+                initial_points: self.initial_points.clone(),
+                topology: self.topology,
+            }
+            .tree(self.usize_width, self.usize_height, self.progress.clone())
+            .context("Failed to generate Wilson's Algorithm tree for image")?,
+            TreeGen::Dfs => trees::DfsTree {
+                rng: self.tree_rng.clone(),
+                initial_point: self.initial_points[0],
+                topology: self.topology,
+            }
+            .tree(self.usize_width, self.usize_height, self.progress.clone())
+            .context("Failed to generate depth-first search tree for image")?,
+            TreeGen::Bfs => trees::BfsTree {
+                rng: self.tree_rng.clone(),
+                initial_points: self.initial_points.clone(),
+                topology: self.topology,
+            }
+            .tree(self.usize_width, self.usize_height, self.progress.clone())
+            .context("Failed to generate breadth-first search tree for image")?,
+            TreeGen::Kruskal => trees::KruskalTree {
+                rng: self.tree_rng.clone(),
+                topology: self.topology,
+            }
+            .tree(self.usize_width, self.usize_height, self.progress.clone())
+            .context("Failed to generate Kruskal's Algorithm tree for image")?,
+            TreeGen::Quad => trees::QuadTree {
+                rng: self.tree_rng.clone(),
+                min_size: config.quad_min_size,
+            }
+            .tree(self.usize_width, self.usize_height, self.progress.clone())
+            .context("Failed to generate quad tree for image")?,
+            TreeGen::Hilbert => trees::HilbertTree
+                .tree(self.usize_width, self.usize_height, self.progress.clone())
+                .context("Failed to generate Hilbert curve tree for image")?,
+        };
+        self.progress.message("Finished generating tree");
+        Ok(tree)
+    }
+
+    /// Remove edges that leave the image, unless its edges are joined
+    fn prune(&self, mut tree: Vec<Neighbours>) -> Result<Vec<Neighbours>> {
+        if !self.topology.tile {
+            prune_edges(
+                self.usize_width,
+                self.usize_height,
+                self.progress.clone(),
+                &mut tree,
+            )
+            .context("Failed to prune tree at edge of grid")?;
+        }
+        self.progress.message("Finished pruning tree");
+        Ok(tree)
+    }
+
+    /// Cut masked-out pixels from a tree, if there's a mask
+    fn mask(&self, mut tree: Vec<Neighbours>) -> Result<Vec<Neighbours>> {
+        if let Some(mask) = &self.mask {
+            let possible_edges = self
+                .topology
+                .possible_edges(self.usize_width, self.usize_height, Progress::new(false))
+                .context("Failed to find possible edges when masking tree")?;
+            mask::apply(
+                &mut tree,
+                mask,
+                (self.usize_width, self.usize_height),
+                &possible_edges,
             )
+            .context("Failed to mask tree")?;
+            self.progress.message("Finished masking tree");
         }
+        Ok(tree)
+    }
+
+    /// The config's built-in colour generator, along with the colour of the
+    /// pixel at each start point
+    fn builtin_colour(&self, config: &GeneratorConfig) -> (AnyColour, Vec<Rgb<u8>>) {
+        let rng = self.colour_rng.clone();
+        let start_colours = self.start_colours.clone();
+        match config.colour_gen {
+            ColourGen::Test => (AnyColour::Test(colour::TestGen), start_colours),
+            ColourGen::Rand => (
+                AnyColour::Rand(colour::RandColour {
+                    step_sizes: config.step_sizes(),
+                    wrap_mode: config.wrap_mode,
+                    rng,
+                }),
+                start_colours,
+            ),
+            ColourGen::Hsv => (
+                AnyColour::Hsv(colour::HsvRandColour {
+                    hue_step: config.hue_step,
+                    sat_step: config.sat_step,
+                    val_step: config.val_step,
+                    rng,
+                }),
+                start_colours,
+            ),
+            ColourGen::Hue => (
+                AnyColour::Hue(colour::HueDrift {
+                    hue_step: config.hue_step,
+                    sat_bounds: (config.min_saturation, config.max_saturation),
+                    val_bounds: (config.min_value, config.max_value),
+                    rng,
+                }),
+                start_colours,
+            ),
+            ColourGen::Gradient => {
+                let gradient = colour::DepthGradient {
+                    from: config.gradient_from,
+                    to: config.gradient_to,
+                    length: config.gradient_length,
+                    bounce: config.gradient_bounce,
+                    depth: 0,
+                };
+                let start_colour = gradient.colour_at(0);
+                (
+                    AnyColour::Gradient(gradient),
+                    vec![start_colour; start_colours.len()],
+                )
+            }
+            ColourGen::Directional => (
+                AnyColour::Directional(colour::DirectionalColour {
+                    step_size: u8::from_step(config.step_size),
+                    rng,
+                }),
+                start_colours,
+            ),
+            ColourGen::Palette | ColourGen::Sampled => {
+                let palette = colour::PaletteColour::from_palette(
+                    &config.palette,
+                    u8::from_step(config.step_size),
+                    rng,
+                );
+                let start_colours = start_colours
+                    .into_iter()
+                    .map(|colour| palette.nearest(colour))
+                    .collect();
+                (AnyColour::Palette(palette), start_colours)
+            }
+            ColourGen::Grey => {
+                let start_colours = start_colours
+                    .into_iter()
+                    .map(|colour| {
+                        let [luma] = colour.to_luma().0;
+                        Rgb([luma; 3])
+                    })
+                    .collect();
+                (
+                    AnyColour::Grey(colour::GreyColour {
+                        step_size: u8::from_step(config.step_size),
+                        rng,
+                    }),
+                    start_colours,
+                )
+            }
+            ColourGen::DirectionHue => (
+                AnyColour::DirectionHue(colour::DirectionHue {
+                    base_sat: config.dir_saturation,
+                    base_val: config.dir_value,
+                    blend_factor: config.dir_blend,
+                    rng,
+                }),
+                start_colours,
+            ),
+            ColourGen::Radial => (
+                AnyColour::Radial(colour::RadialColour {
+                    step_size: u8::from_step(config.step_size),
+                    centre: (config.x, config.y),
+                    rng,
+                }),
+                start_colours,
+            ),
+            ColourGen::Noise => {
+                let mut rng = rng;
+                // far enough apart that the channels don't follow each other
+                let mut offset = || rng.gen_range(0. ..1e6);
+                let noise = colour::NoiseColour {
+                    scale: config.noise_scale,
+                    octaves: config.noise_octaves,
+                    persistence: config.noise_persistence,
+                    offset_r: offset(),
+                    offset_g: offset(),
+                    offset_b: offset(),
+                    depth: 0,
+                };
+                let start_colour = noise.colour_at(0);
+                (
+                    AnyColour::Noise(noise),
+                    vec![start_colour; start_colours.len()],
+                )
+            }
+            ColourGen::Blend => (
+                AnyColour::Blend(colour::BlendColour {
+                    a: colour::RandColour {
+                        step_sizes: config.step_sizes(),
+                        wrap_mode: config.wrap_mode,
+                        rng,
+                    },
+                    b: colour::TestGen,
+                    blend: config.blend_factor,
+                }),
+                start_colours,
+            ),
+        }
+    }
+
+    /// Settings for plotting the image, starting the animation if there is one
+    fn plotting(&self, config: &GeneratorConfig) -> Result<Plotting> {
+        let animation = config
+            .animate
+            .as_ref()
+            .map(|path| {
+                let pacing = Pacing {
+                    frame_every: config.frame_every,
+                    frames: config.animation_frames,
+                    fps: config.animation_fps,
+                };
+                Animation::create(path, (self.width, self.height), pacing)
+            })
+            .transpose()
+            .context("Failed to start animation")?;
+        Ok(Plotting {
+            split_at: config.split_at,
+            max_fanout: config.max_fanout,
+            background: self.mask.is_some().then_some(config.mask_background),
+            progress: self.progress.clone(),
+            animation,
+            interrupt: config.interrupt.clone(),
+            topology: self.topology,
+            symmetry: config.symmetry,
+            size: (config.width, config.height),
+        })
     }
-    .context("Failed to place colours on image")?;
-    eprintln!("Coloured pixels placed");
-    Ok(buf)
 }
 
-trait GenTree: Sync + Send {
-    fn tree(
-        &mut self,
-        width: usize,
-        height: usize,
+/// Reports the progress of generation, unless it's been turned off
+#[derive(Debug, Clone)]
+pub struct Progress {
+    style: ProgressStyle,
+    verbosity: Verbosity,
+    /// Where bars are drawn, if they're going to a terminal
+    bars: Option<Arc<Bars>>,
+    /// Where progress is written, if it's going to a file
+    log: Option<Arc<ProgressLog>>,
+}
+
+/// Progress bars drawn together, each on its own line, so bars that are active
+/// at the same time don't draw over each other
+#[derive(Debug)]
+struct Bars {
+    multi: Arc<MultiProgress>,
+    /// Line that status messages are printed above, which keeps the bars
+    /// drawing until generation is over
+    status: ProgressBar,
+    /// Whether the bars are on stderr, where status messages go too
+    on_stderr: bool,
+    drawer: Option<JoinHandle<()>>,
+}
+
+/// Progress written to a file as lines like `Prim's Algorithm - 40% done`,
+/// for each stage that's moved on since the last lines were written
+#[derive(Debug)]
+struct ProgressLog {
+    stages: Arc<Mutex<Vec<LoggedStage>>>,
+    /// Dropped to tell the writing thread to write a last time and stop
+    stop: Option<Sender<()>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct LoggedStage {
+    name: Cow<'static, str>,
+    bar: ProgressBar,
+    /// Position of the bar when its last line was written
+    written: Option<u64>,
+}
+
+/// How often progress is written to a file
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Progress {
+    /// Start reporting progress, drawing bars only if `visible` and stderr is
+    /// a terminal
+    pub fn new(visible: bool) -> Progress {
+        let bars = (visible && io::stderr().is_terminal())
+            .then(|| Bars::start(ProgressDrawTarget::stderr(), true));
+        Progress::reporting(bars, None)
+    }
+
+    /// Start reporting progress to an output
+    ///
+    /// Fails if the output is a file that can't be created.
+    pub fn to(output: &ProgressOutput) -> Result<Progress> {
+        Ok(match output {
+            ProgressOutput::Stderr => Progress::new(true),
+            ProgressOutput::Stdout => {
+                let bars = io::stdout()
+                    .is_terminal()
+                    .then(|| Bars::start(ProgressDrawTarget::stdout(), false));
+                Progress::reporting(bars, None)
+            }
+            ProgressOutput::Hidden => Progress::new(false),
+            ProgressOutput::File(path) => {
+                let file = File::create(path).with_context(|| {
+                    format!("Failed to create progress file {}", path.display())
+                })?;
+                Progress::reporting(None, Some(Arc::new(ProgressLog::start(file))))
+            }
+        })
+    }
+
+    fn reporting(bars: Option<Arc<Bars>>, log: Option<Arc<ProgressLog>>) -> Progress {
+        // Progress bar template
+        let style = ProgressStyle::default_bar()
+            .progress_chars("## ")
+            .template("[{bar}] {prefix} - {percent}% done, {eta} left - {msg}");
+        Progress {
+            style,
+            verbosity: Verbosity::silent(),
+            bars,
+            log,
+        }
+    }
+
+    /// Log each stage of generation as it finishes
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Progress {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// A progress bar for one stage of generation
+    pub fn bar(&self, len: u64, prefix: impl Into<Cow<'static, str>>) -> ProgressBar {
+        self.styled_bar(len, prefix, self.style.clone())
+    }
+
+    /// A progress bar with its own style, for when the usual template doesn't
+    /// fit
+    pub fn styled_bar(
+        &self,
+        len: u64,
+        prefix: impl Into<Cow<'static, str>>,
         style: ProgressStyle,
-    ) -> Result<Vec<Neighbours>>;
+    ) -> ProgressBar {
+        let prefix = prefix.into();
+        let bar = match (&self.bars, &self.log) {
+            (Some(bars), _) => {
+                let bar = bars.multi.add(ProgressBar::new(len));
+                // every redraw is sent to the drawing thread, so skip most
+                bar.set_draw_delta(len / 1000);
+                bar
+            }
+            (None, Some(log)) => {
+                // hidden bars still keep count for the log to read
+                let bar = ProgressBar::with_draw_target(len, ProgressDrawTarget::hidden());
+                if let Ok(mut stages) = log.stages.lock() {
+                    stages.push(LoggedStage {
+                        name: prefix.clone(),
+                        bar: bar.clone(),
+                        written: None,
+                    });
+                }
+                bar
+            }
+            (None, None) => ProgressBar::hidden(),
+        };
+        bar.with_style(style).with_prefix(prefix)
+    }
+
+    /// Log a stage of generation with its timestamp, if verbose
+    pub fn message(&self, message: impl Display) {
+        if let Some(line) = self.verbosity.line(message) {
+            self.println(line);
+        }
+    }
+
+    /// Print a warning, whether or not anything else is shown
+    pub fn warn(&self, message: impl Display) {
+        self.println(format_args!("Warning: {}", message));
+    }
+
+    /// Print a line on stderr, above the bars if they're being drawn there
+    fn println(&self, line: impl Display) {
+        match &self.bars {
+            Some(bars) if bars.on_stderr => bars.status.println(line.to_string()),
+            _ => eprintln!("{}", line),
+        }
+    }
+}
+
+impl Bars {
+    fn start(target: ProgressDrawTarget, on_stderr: bool) -> Arc<Bars> {
+        let multi = Arc::new(MultiProgress::with_draw_target(target));
+        let status = multi.add(ProgressBar::new_spinner().with_style(
+            ProgressStyle::default_spinner().template("{spinner} Generating for {elapsed}"),
+        ));
+        status.enable_steady_tick(100);
+        let drawer = {
+            let multi = multi.clone();
+            // bars are only drawn while something is joined to them
+            thread::spawn(move || {
+                multi.join().ok();
+            })
+        };
+        Arc::new(Bars {
+            multi,
+            status,
+            on_stderr,
+            drawer: Some(drawer),
+        })
+    }
+}
+
+impl Drop for Bars {
+    fn drop(&mut self) {
+        // every other bar has finished, so this lets the drawing thread end
+        self.status.finish_and_clear();
+        if let Some(drawer) = self.drawer.take() {
+            drawer.join().ok();
+        }
+    }
+}
+
+impl ProgressLog {
+    fn start(file: File) -> ProgressLog {
+        let stages = Arc::new(Mutex::new(Vec::new()));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let writer = {
+            let stages = stages.clone();
+            thread::spawn(move || {
+                let mut file = BufWriter::new(file);
+                loop {
+                    let last = !matches!(
+                        stopped.recv_timeout(PROGRESS_LOG_INTERVAL),
+                        Err(RecvTimeoutError::Timeout)
+                    );
+                    if let Ok(mut stages) = stages.lock() {
+                        ProgressLog::write_lines(&mut file, &mut stages).ok();
+                    }
+                    if last {
+                        break;
+                    }
+                }
+            })
+        };
+        ProgressLog {
+            stages,
+            stop: Some(stop),
+            writer: Some(writer),
+        }
+    }
+
+    /// Write a line for each stage that's moved on, forgetting stages once
+    /// they've finished
+    fn write_lines(file: &mut BufWriter<File>, stages: &mut Vec<LoggedStage>) -> io::Result<()> {
+        let mut result = Ok(());
+        stages.retain_mut(|stage| {
+            let position = stage.bar.position();
+            if stage.written != Some(position) {
+                let percent = (position * 100)
+                    .checked_div(stage.bar.length())
+                    .unwrap_or(100);
+                if let Err(e) = writeln!(file, "{} - {}% done", stage.name, percent) {
+                    result = Err(e);
+                }
+                stage.written = Some(position);
+            }
+            !stage.bar.is_finished()
+        });
+        result?;
+        file.flush()
+    }
+}
+
+impl Drop for ProgressLog {
+    fn drop(&mut self) {
+        // disconnecting wakes the writing thread for its last lines
+        self.stop.take();
+        if let Some(writer) = self.writer.take() {
+            writer.join().ok();
+        }
+    }
+}
+
+/// Read the image whose pixels give each start point its colour, scaling it
+/// to the size of the image being generated if it doesn't match
+fn load_start_image(
+    path: &Path,
+    (width, height): (u32, u32),
+    progress: &Progress,
+) -> Result<RgbImage> {
+    let reference = ::image::open(path)
+        .with_context(|| format!("Failed to read start image {}", path.display()))?
+        .into_rgb8();
+    if reference.dimensions() == (width, height) {
+        return Ok(reference);
+    }
+    progress.warn(format_args!(
+        "start image {} is {}x{}, so it's been scaled to {}x{}",
+        path.display(),
+        reference.width(),
+        reference.height(),
+        width,
+        height
+    ));
+    Ok(imageops::resize(
+        &reference,
+        width,
+        height,
+        FilterType::Lanczos3,
+    ))
+}
+
+/// Convert a coordinate in 0..=1 into a pixel index along an axis, so that
+/// 1.0 maps to the last pixel rather than one past the end
+fn fraction_to_pixel(fraction: f64, size: usize) -> usize {
+    ((fraction * size as f64) as usize).min(size.saturating_sub(1))
+}
+
+/// Something that can connect every pixel of an image into a spanning tree
+pub trait GenTree: Sync + Send {
+    /// Build a tree over a grid of pixels, as the set of neighbours each pixel
+    /// in row-major order is joined to
+    ///
+    /// Every edge should be recorded at both of its ends. Edges that leave the
+    /// image are removed afterwards unless it's tiled.
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>>;
+}
+
+/// Subpixel types that colour generators can produce
+pub trait ColourChannel: Primitive + SampleUniform + Send + Sync + 'static {
+    fn saturating_add(self, other: Self) -> Self;
+    fn saturating_sub(self, other: Self) -> Self;
+    /// Add an offset, carrying on from the other end of the channel's range
+    /// past either end
+    fn wrapping_offset(self, offset: i32) -> Self;
+    /// Add an offset, bouncing back off either end of the channel's range
+    fn mirroring_offset(self, offset: i32) -> Self;
+    /// Convert a step size, saturating at the largest channel value
+    fn from_step(step_size: u16) -> Self;
+    /// Scale an 8-bit value onto the full channel range
+    fn from_8_bit(value: u8) -> Self;
+    /// Scale the channel down to 8 bits, rounding to nearest
+    fn to_8_bit(self) -> u8;
+    /// Interpolate linearly towards another value, with `t` in 0..=1
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl ColourChannel for u8 {
+    fn saturating_add(self, other: Self) -> Self {
+        u8::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        u8::saturating_sub(self, other)
+    }
+
+    fn wrapping_offset(self, offset: i32) -> Self {
+        (i32::from(self) + offset).rem_euclid(i32::from(u8::MAX) + 1) as u8
+    }
+
+    fn mirroring_offset(self, offset: i32) -> Self {
+        let max = i32::from(u8::MAX);
+        let reflected = (i32::from(self) + offset).rem_euclid(2 * max);
+        (if reflected > max {
+            2 * max - reflected
+        } else {
+            reflected
+        }) as u8
+    }
+
+    fn from_step(step_size: u16) -> Self {
+        u8::try_from(step_size).unwrap_or(u8::MAX)
+    }
+
+    fn from_8_bit(value: u8) -> Self {
+        value
+    }
+
+    fn to_8_bit(self) -> u8 {
+        self
+    }
+
+    fn lerp(self, other: Self, t: f32) -> Self {
+        let (from, to) = (f32::from(self), f32::from(other));
+        (from + (to - from) * t).round().clamp(0., 255.) as u8
+    }
+}
+
+impl ColourChannel for u16 {
+    fn saturating_add(self, other: Self) -> Self {
+        u16::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        u16::saturating_sub(self, other)
+    }
+
+    fn wrapping_offset(self, offset: i32) -> Self {
+        (i32::from(self) + offset).rem_euclid(i32::from(u16::MAX) + 1) as u16
+    }
+
+    fn mirroring_offset(self, offset: i32) -> Self {
+        let max = i32::from(u16::MAX);
+        let reflected = (i32::from(self) + offset).rem_euclid(2 * max);
+        (if reflected > max {
+            2 * max - reflected
+        } else {
+            reflected
+        }) as u16
+    }
+
+    fn from_step(step_size: u16) -> Self {
+        step_size
+    }
+
+    fn from_8_bit(value: u8) -> Self {
+        u16::from(value) * 257
+    }
+
+    fn to_8_bit(self) -> u8 {
+        ((u32::from(self) + 128) / 257) as u8
+    }
+
+    fn lerp(self, other: Self, t: f32) -> Self {
+        let (from, to) = (f32::from(self), f32::from(other));
+        (from + (to - from) * t).round().clamp(0., 65535.) as u16
+    }
+}
+
+/// Pixel types that colour generators can produce
+///
+/// Generators that only understand 8-bit RGB see the colour scaled down to
+/// 8 bits per channel.
+pub trait ColourPixel: Pixel<Subpixel = Self::Channel> + Send + Sync + 'static {
+    type Channel: ColourChannel;
+    /// The colour channels of the pixel at 8 bits each, without any alpha
+    fn rgb(self) -> Rgb<u8>;
+    /// Replace the colour channels of the pixel, keeping any alpha
+    fn with_rgb(self, rgb: Rgb<u8>) -> Self;
+    /// The pixel at 8 bits per channel, opaque if it has no alpha
+    fn rgba(self) -> Rgba<u8> {
+        let Rgb([r, g, b]) = self.rgb();
+        Rgba([r, g, b, u8::MAX])
+    }
+    /// Replace the alpha channel of the pixel, if it has one
+    fn with_alpha(self, _alpha: u8) -> Self {
+        self
+    }
+}
+
+impl<C: ColourChannel> ColourPixel for Rgb<C> {
+    type Channel = C;
+
+    fn rgb(self) -> Rgb<u8> {
+        Rgb(self.0.map(C::to_8_bit))
+    }
+
+    fn with_rgb(self, rgb: Rgb<u8>) -> Self {
+        Rgb(rgb.0.map(C::from_8_bit))
+    }
+}
+
+impl<C: ColourChannel> ColourPixel for Rgba<C> {
+    type Channel = C;
+
+    fn rgb(self) -> Rgb<u8> {
+        let [r, g, b, _] = self.0.map(C::to_8_bit);
+        Rgb([r, g, b])
+    }
+
+    fn with_rgb(self, rgb: Rgb<u8>) -> Self {
+        let [r, g, b] = rgb.0.map(C::from_8_bit);
+        Rgba([r, g, b, self[3]])
+    }
+
+    fn rgba(self) -> Rgba<u8> {
+        Rgba(self.0.map(C::to_8_bit))
+    }
+
+    fn with_alpha(self, alpha: u8) -> Self {
+        let [r, g, b, _] = self.0;
+        Rgba([r, g, b, C::from_8_bit(alpha)])
+    }
 }
 
-trait GenColour: Sync + Send {
-    fn colour(&mut self, old_colour: Rgb<u8>, direction_into: Neighbours) -> Rgb<u8>;
+/// Something that can pick the colour of each pixel from its parent's colour
+pub trait GenColour<P: ColourPixel>: Sync + Send {
+    /// The colour of a pixel, given the colour of its parent in the tree and
+    /// the direction from the parent to the pixel
+    ///
+    /// `position` is the pixel's `(row, col)` in an image whose size is
+    /// `dimensions`, as `(height, width)`.
+    fn colour(
+        &mut self,
+        old_colour: P,
+        direction_into: Neighbours,
+        position: (u32, u32),
+        dimensions: (u32, u32),
+    ) -> P;
+    /// A generator for a child branch of the tree
+    ///
+    /// Branches are coloured in parallel, so any random number generator
+    /// should be split rather than cloned.
+    #[allow(clippy::wrong_self_convention)]
     fn new(&mut self) -> Self;
 }
 
-fn prune_edges(
+/// Remove the directions that lead off the edge of a grid of pixels
+///
+/// Pixels in the top row lose their northward directions, the bottom row
+/// their southward ones, and likewise for the left and right columns.
+pub fn prune_edges(
     width: usize,
     height: usize,
-    style: ProgressStyle,
-    grid: &mut Vec<Neighbours>,
+    progress: Progress,
+    grid: &mut [Neighbours],
 ) -> Result<()> {
-    let main_bar = ProgressBar::new(4)
-        .with_style(style)
-        .with_prefix("Pruning edges");
+    let main_bar = progress.bar(4, "Pruning edges");
     main_bar.tick();
     for (row, flag) in [
         (0, Neighbours::NORTHWARD),
@@ -178,91 +1283,635 @@ fn prune_edges(
     Ok(())
 }
 
-fn lay_colours<G: GenColour + 'static>(
-    tree: Arc<Vec<Neighbours>>,
-    root: (u32, u32),
-    colour: Rgb<u8>,
+/// Which neighbours each pixel is allowed to connect to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct Topology {
+    pub(crate) connectivity: Connectivity,
+    /// Whether opposite edges of the image are joined so it tiles seamlessly
+    pub(crate) tile: bool,
+}
+
+impl Topology {
+    /// Every edge a tree generator could use, for each pixel
+    fn possible_edges(
+        self,
+        width: usize,
+        height: usize,
+        progress: Progress,
+    ) -> Result<Vec<Neighbours>> {
+        let mut possible_edges = vec![Neighbours::all(); width * height];
+        if !self.tile {
+            prune_edges(width, height, progress, &mut possible_edges)
+                .context("Failed to prune edges of complete grid")?;
+        }
+        restrict_connectivity(self.connectivity, &mut possible_edges);
+        Ok(possible_edges)
+    }
+
+    /// Move a point in a direction, wrapping around if the image is tiled
+    ///
+    /// Fails if the step would leave an untiled image, which means the tree
+    /// has an edge off the grid, such as one loaded from a tiled image.
+    pub(crate) fn step(
+        self,
+        direction: Neighbours,
+        position: (u32, u32),
+        dimensions: (u32, u32),
+    ) -> Result<(u32, u32)> {
+        if self.tile {
+            return Ok(direction.step_wrapping(position, dimensions));
+        }
+        direction
+            .step_checked(position, dimensions)
+            .with_context(|| off_grid(direction, position))
+    }
+
+    /// Move a point in a direction like [`Topology::step`], with the point
+    /// represented by usize coordinates
+    pub(crate) fn step_usize(
+        self,
+        direction: Neighbours,
+        position: (usize, usize),
+        dimensions: (usize, usize),
+    ) -> Result<(usize, usize)> {
+        if self.tile {
+            return Ok(direction.step_wrapping_usize(position, dimensions));
+        }
+        direction
+            .step_checked_usize(position, dimensions)
+            .with_context(|| off_grid(direction, position))
+    }
+}
+
+/// Error message for an edge leading off an untiled image
+fn off_grid(direction: Neighbours, (row, col): (impl Display, impl Display)) -> String {
+    format!(
+        "Edge {:?} out of pixel {},{} leads off the image, which isn't tiled",
+        direction, col, row
+    )
+}
+
+/// Remove diagonal directions from every pixel if only orthogonal neighbours
+/// are allowed
+fn restrict_connectivity(connectivity: Connectivity, grid: &mut [Neighbours]) {
+    if connectivity == Connectivity::Four {
+        for point in grid {
+            point.remove(Neighbours::DIAGONAL);
+        }
+    }
+}
+
+/// Settings for plotting pixels that don't depend on the pixel type
+struct Plotting {
+    /// Number of branches waiting in a task before half are shared with a new
+    /// parallel task
+    split_at: usize,
+    /// Most children of one pixel that can be shared with other tasks
+    max_fanout: usize,
+    /// Colour of pixels the tree doesn't reach, if they might not all be
+    /// reached
+    background: Option<Rgb<u8>>,
+    progress: Progress,
+    animation: Option<Animation>,
+    interrupt: Option<Interrupt>,
+    topology: Topology,
+    symmetry: Symmetry,
+    /// Dimensions of the whole image, once it's been mirrored
+    size: (u32, u32),
+}
+
+/// Allocate an image and colour it in by walking the tree from the roots
+///
+/// Each root's pixel is `colour` with its colour channels replaced by the
+/// root's colour, so `colour` only sets the alpha.
+fn colour_image<P: ColourPixel, G: GenColour<P> + 'static>(
+    tree: Vec<Neighbours>,
+    roots: &[((u32, u32), Rgb<u8>)],
+    colour: P,
     colour_gen: G,
-    mut image: ImageBuffer<Rgb<u8>, Vec<u8>>,
-    style: ProgressStyle,
-) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+    (width, height): (u32, u32),
+    plotting: Plotting,
+) -> Result<ImageBuffer<P, Vec<P::Channel>>> {
+    let buf = match plotting.background {
+        Some(background) => ImageBuffer::from_pixel(
+            width,
+            height,
+            colour.with_rgb(background).with_alpha(u8::MAX),
+        ),
+        None => ImageBuffer::new(width, height),
+    };
+    plotting.progress.message("Empty buffer allocated");
+    let (symmetry, size) = (plotting.symmetry, plotting.size);
+    let progress = plotting.progress.clone();
+    let roots = roots
+        .iter()
+        .map(|&(root, rgb)| (root, colour.with_rgb(rgb)))
+        .collect::<Vec<_>>();
+    let grid = Grid {
+        tree,
+        topology: plotting.topology,
+        dimensions: (height, width),
+    };
+    let buf = lay_colours(Arc::new(grid), &roots, colour_gen, buf, plotting)
+        .context("Failed to place colours on image")?;
+    if symmetry == Symmetry::None {
+        return Ok(buf);
+    }
+    let mirrored = mirror(&buf, size, symmetry);
+    progress.message("Image mirrored");
+    Ok(mirrored)
+}
+
+/// Fill an image by reflecting the top left of it, which is already coloured
+/// in, across the middle
+///
+/// Pixels with the same position counting in from opposite edges match, so
+/// the middle column or row of an odd width or height is only in the top left.
+fn mirror<P: ColourPixel>(
+    top_left: &ImageBuffer<P, Vec<P::Channel>>,
+    (width, height): (u32, u32),
+    symmetry: Symmetry,
+) -> ImageBuffer<P, Vec<P::Channel>> {
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let x = if symmetry.horizontal() {
+            x.min(width - 1 - x)
+        } else {
+            x
+        };
+        let y = if symmetry.vertical() {
+            y.min(height - 1 - y)
+        } else {
+            y
+        };
+        *top_left.get_pixel(x, y)
+    })
+}
+
+/// Draw a tree as a maze, with each pixel a white cell in a grid of black
+/// walls
+///
+/// The maze is `2 * width + 1` by `2 * height + 1` pixels. Cell `(row, col)`
+/// is at `(2 * col + 1, 2 * row + 1)`, and the wall between two cells is
+/// knocked out if they're joined. Walls are only between orthogonal
+/// neighbours, so trees with diagonal edges are rejected. Edges that wrap
+/// around a tiled image open both sides of the border.
+pub fn render_maze(width: usize, height: usize, tree: &[Neighbours]) -> Result<GrayImage> {
+    if tree.len() != width * height {
+        bail!(
+            "Tree has {} pixels, but the maze has {}",
+            tree.len(),
+            width * height
+        );
+    }
+    if let Some(index) = tree
+        .iter()
+        .position(|point| point.intersects(Neighbours::DIAGONAL))
+    {
+        bail!(
+            "Pixel {},{} has a diagonal edge, which mazes can't show; use --connectivity 4",
+            index % width,
+            index / width
+        );
+    }
+    let size =
+        |cells: usize| u32::try_from(2 * cells + 1).context("Maze is too large for an image");
+    let mut maze = GrayImage::new(size(width)?, size(height)?);
+    for (index, &point) in tree.iter().enumerate() {
+        // centre of the cell, in maze pixels
+        let (x, y) = (2 * (index % width) + 1, 2 * (index / width) + 1);
+        maze.put_pixel(x as u32, y as u32, MAZE_CELL);
+        for direction in point {
+            let (x, y) = match direction {
+                Neighbours::NORTH => (x, y - 1),
+                Neighbours::EAST => (x + 1, y),
+                Neighbours::SOUTH => (x, y + 1),
+                _ => (x - 1, y),
+            };
+            maze.put_pixel(x as u32, y as u32, MAZE_CELL);
+        }
+    }
+    Ok(maze)
+}
+
+/// Colour of a maze's cells and the gaps between them
+const MAZE_CELL: Luma<u8> = Luma([u8::MAX]);
+
+fn lay_colours<P: ColourPixel, G: GenColour<P> + 'static, B: Branches>(
+    branches: Arc<B>,
+    roots: &[((u32, u32), P)],
+    mut colour_gen: G,
+    image: ImageBuffer<P, Vec<P::Channel>>,
+    Plotting {
+        split_at,
+        max_fanout,
+        progress,
+        animation,
+        interrupt,
+        ..
+    }: Plotting,
+) -> Result<ImageBuffer<P, Vec<P::Channel>>> {
+    if interrupt
+        .as_ref()
+        .is_some_and(|interrupt| !interrupt.begin_colouring())
+    {
+        bail!("Interrupted before any pixels were placed");
+    }
     let (height, width) = (image.height(), image.width());
     let num_pixels = width * height;
-    let bar = ProgressBar::new(num_pixels.into())
-        .with_style(style)
-        .with_prefix("Plotting pixels");
+    let bar = progress.bar(num_pixels.into(), "Plotting pixels");
     bar.tick();
-    let (enqueue_pixel, dequeue_pixel) = channel();
-    let handle = thread::spawn(move || {
-        for ((row, col), colour) in dequeue_pixel {
-            image.put_pixel(col, row, colour);
-            bar.inc(1);
-        }
-        bar.finish_with_message("Done");
-        image
-    });
+    let canvas = Arc::new(Mutex::new(Canvas::new(image, animation, bar, interrupt)));
+    let (&(first_root, first_colour), other_roots) = roots
+        .split_first()
+        .context("No root to start colouring from")?;
+    let fanout = Fanout {
+        split_at,
+        max_fanout,
+    };
     scope(|thread_scope| {
-        lay_colours_in_subtree(
+        // each extra root is the start of a separate tree in the forest
+        for &(root, colour) in other_roots {
+            let frame = Frame {
+                position: root,
+                visited_directions: Neighbours::empty(),
+                colour,
+                colour_gen: colour_gen.new(),
+            };
+            spawn_subtrees(thread_scope, &branches, vec![frame], fanout, &canvas);
+        }
+        lay_colours_in_subtrees(
             thread_scope,
-            tree,
-            root,
-            Neighbours::empty(),
-            colour,
-            colour_gen,
-            (height, width),
-            enqueue_pixel,
+            branches,
+            vec![Frame {
+                position: first_root,
+                visited_directions: Neighbours::empty(),
+                colour: first_colour,
+                colour_gen,
+            }],
+            fanout,
+            canvas.clone(),
         )
     })
     .context("Failed to assign colours to the image")?;
-    match handle.join() {
-        Ok(image) => Ok(image),
-        Err(_) => bail!("Failed to join image-mutator thread"),
+    let Canvas {
+        image,
+        animation,
+        animation_result,
+        task_result,
+        bar,
+        interrupt,
+        spawned_tasks,
+        peak_running_tasks,
+        ..
+    } = Arc::try_unwrap(canvas)
+        .ok()
+        .context("Image still shared after every task finished")?
+        .into_inner()
+        .ok()
+        .context("Image lock poisoned by a failed task")?;
+    if let Some(interrupt) = &interrupt {
+        interrupt.end_colouring();
+    }
+    task_result.context("Failed to assign colours to the image")?;
+    if interrupt.as_ref().is_some_and(Interrupt::is_stopped) {
+        bar.abandon_with_message("Interrupted");
+    } else {
+        bar.finish_with_message("Done");
     }
+    progress.message(format_args!(
+        "Spawned {} parallel tasks, up to {} at once",
+        spawned_tasks, peak_running_tasks
+    ));
+    animation_result
+        .and_then(|()| match animation {
+            Some(anim) => anim.finish(),
+            None => Ok(()),
+        })
+        .context("Failed to write animation")?;
+    Ok(image)
 }
 
-fn lay_colours_in_subtree<G: GenColour + 'static>(
-    thread_scope: &Scope,
-    tree: Arc<Vec<Neighbours>>,
-    (root_row, root_col): (u32, u32),
+/// The branches of a tree, as followed outward from its roots while colouring
+trait Branches: Send + Sync + 'static {
+    /// Height and width of the image the tree covers
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Call `child` with the direction to and position of each pixel joined
+    /// to a pixel, other than those in `visited_directions`
+    fn for_each_child(
+        &self,
+        position: (u32, u32),
+        visited_directions: Neighbours,
+        child: impl FnMut(Neighbours, (u32, u32)),
+    ) -> Result<()>;
+}
+
+/// A tree stored as the directions each pixel is joined to its neighbours in
+struct Grid {
+    tree: Vec<Neighbours>,
+    topology: Topology,
+    /// Height and width of the image
+    dimensions: (u32, u32),
+}
+
+impl Branches for Grid {
+    fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn for_each_child(
+        &self,
+        (row, col): (u32, u32),
+        visited_directions: Neighbours,
+        mut child: impl FnMut(Neighbours, (u32, u32)),
+    ) -> Result<()> {
+        // tree must not contain any cycles
+        let index = row * self.dimensions.1 + col;
+        let &tree_directions = self
+            .tree
+            .get(usize::try_from(index).context("Failed to convert index u32 to usize")?)
+            .context("Index out of bounds reading from tree")?;
+        for direction in tree_directions - visited_directions {
+            child(
+                direction,
+                self.topology.step(direction, (row, col), self.dimensions)?,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The image being coloured, along with everything else that follows the
+/// order pixels are placed in
+struct Canvas<P: ColourPixel> {
+    image: ImageBuffer<P, Vec<P::Channel>>,
+    animation: Option<Animation>,
+    /// First error from the animation, after which it's dropped
+    animation_result: Result<()>,
+    /// First error from a parallel task
+    task_result: Result<()>,
+    bar: ProgressBar,
+    interrupt: Option<Interrupt>,
+    /// Parallel tasks spawned in total
+    spawned_tasks: usize,
+    /// Parallel tasks spawned that haven't finished yet
+    running_tasks: usize,
+    /// Most parallel tasks that were ever spawned and unfinished at once
+    peak_running_tasks: usize,
+}
+
+impl<P: ColourPixel> Canvas<P> {
+    fn new(
+        image: ImageBuffer<P, Vec<P::Channel>>,
+        animation: Option<Animation>,
+        bar: ProgressBar,
+        interrupt: Option<Interrupt>,
+    ) -> Canvas<P> {
+        Canvas {
+            image,
+            animation,
+            animation_result: Ok(()),
+            task_result: Ok(()),
+            bar,
+            interrupt,
+            spawned_tasks: 0,
+            running_tasks: 0,
+            peak_running_tasks: 0,
+        }
+    }
+
+    /// Write a batch of coloured pixels, returning whether to carry on placing
+    /// more
+    fn place(&mut self, batch: &[((u32, u32), P)]) -> bool {
+        for &((row, col), colour) in batch {
+            self.image.put_pixel(col, row, colour);
+            if let Some(anim) = &mut self.animation {
+                self.animation_result = anim.place((row, col), colour.rgba());
+                if self.animation_result.is_err() {
+                    // keep plotting, but stop trying to animate
+                    self.animation = None;
+                }
+            }
+        }
+        self.bar.inc(batch.len() as u64);
+        !self.interrupt.as_ref().is_some_and(Interrupt::is_stopped)
+    }
+}
+
+/// A pixel waiting to be coloured, along with the state needed to colour its
+/// subtree
+struct Frame<P, G> {
+    position: (u32, u32),
     visited_directions: Neighbours,
-    initial_colour: Rgb<u8>,
-    mut colour_gen: G,
-    (height, width): (u32, u32),
-    enqueue_pixel: Sender<((u32, u32), Rgb<u8>)>,
+    colour: P,
+    colour_gen: G,
+}
+
+/// Limits on how colouring work is shared between parallel tasks
+#[derive(Debug, Copy, Clone)]
+struct Fanout {
+    /// Number of branches waiting in a task before half are shared with a new
+    /// parallel task
+    split_at: usize,
+    /// Most children of one pixel that can be shared with other tasks
+    max_fanout: usize,
+}
+
+/// Number of coloured pixels a task collects before locking the image to
+/// write them
+const PIXEL_BATCH: usize = 1024;
+
+/// Colour every pixel in the subtrees below some frames
+///
+/// Frames are worked through from explicit stacks rather than by recursion,
+/// so deep trees can't overflow the worker thread's stack. Whenever more than
+/// `split_at` frames are waiting, the older half of those that can be shared
+/// is handed to a new parallel task, so tasks are only spawned while there are
+/// branches to spare. Only the first `max_fanout` children of each pixel can
+/// be shared; the rest are always coloured by the task that reached it, so a
+/// pixel with a huge number of children can't flood the pool with tasks.
+fn lay_colours_in_subtrees<P: ColourPixel, G: GenColour<P> + 'static, B: Branches>(
+    thread_scope: &Scope,
+    branches: Arc<B>,
+    mut shareable: Vec<Frame<P, G>>,
+    fanout: Fanout,
+    canvas: Arc<Mutex<Canvas<P>>>,
 ) -> Result<()> {
-    // tree must not contain any cycles
-    let index = root_row * width + root_col;
-    let &tree_directions = tree
-        .get(usize::try_from(index).context("Failed to convert index u32 to usize")?)
-        .context("Index out of bounds reading from tree")?;
-    let unvisited_directions = tree_directions - visited_directions;
-    // Add new colour to image
-    enqueue_pixel
-        .send(((root_row, root_col), initial_colour))
-        .context("Main thread closed connection before all workers finished")?;
-    // Check next directions
-    for &child in Neighbours::DIRECTIONS
-        .iter()
-        .filter(|&&dir| unvisited_directions.contains(dir))
+    let place = |batch: &[((u32, u32), P)]| -> Result<bool> {
+        Ok(canvas
+            .lock()
+            .ok()
+            .context("Image lock poisoned by a failed task")?
+            .place(batch))
+    };
+    let dimensions = branches.dimensions();
+    let mut inline = Vec::new();
+    let mut batch = Vec::with_capacity(PIXEL_BATCH);
+    while let Some(Frame {
+        position,
+        visited_directions,
+        colour: initial_colour,
+        mut colour_gen,
+    }) = inline.pop().or_else(|| shareable.pop())
     {
-        let enqueue_pixel = enqueue_pixel.clone();
-        let tree = tree.clone();
-        let new_colour = colour_gen.colour(initial_colour, child);
-        let new_colour_gen = colour_gen.new();
-        let (row, col) = child.step((root_row, root_col));
-        thread_scope.spawn(move |s| {
-            lay_colours_in_subtree(
-                s,
-                tree,
-                (row, col),
-                child.reverse().unwrap_or(Neighbours::empty()),
-                new_colour,
-                new_colour_gen,
-                (height, width),
-                enqueue_pixel.clone(),
-            )
-            .unwrap_or_else(|e| panic!("Thread panicking due to error:\n{}\n", e));
+        // Add new colour to image
+        batch.push((position, initial_colour));
+        if batch.len() >= PIXEL_BATCH {
+            if !place(&batch)? {
+                // interrupted, so drop the rest of the stack
+                return Ok(());
+            }
+            batch.clear();
+        }
+        // Check next directions
+        let mut children = 0;
+        branches.for_each_child(position, visited_directions, |child, child_position| {
+            let frame = Frame {
+                colour: colour_gen.colour(initial_colour, child, child_position, dimensions),
+                colour_gen: colour_gen.new(),
+                position: child_position,
+                visited_directions: child.reverse().unwrap_or(Neighbours::empty()),
+            };
+            if children < fanout.max_fanout {
+                shareable.push(frame);
+            } else {
+                inline.push(frame);
+            }
+            children += 1;
+        })?;
+        if shareable.len() + inline.len() > fanout.split_at && !shareable.is_empty() {
+            // the oldest frames are nearest the root, so likely have the
+            // biggest subtrees to share out
+            let shared = shareable.drain(..shareable.len().div_ceil(2)).collect();
+            spawn_subtrees(thread_scope, &branches, shared, fanout, &canvas);
+        }
+    }
+    place(&batch).map(drop)
+}
+
+/// Colour the subtrees below some frames in a new parallel task
+fn spawn_subtrees<'scope, P: ColourPixel, G: GenColour<P> + 'static, B: Branches>(
+    thread_scope: &Scope<'scope>,
+    branches: &Arc<B>,
+    frames: Vec<Frame<P, G>>,
+    fanout: Fanout,
+    canvas: &Arc<Mutex<Canvas<P>>>,
+) {
+    if let Ok(mut canvas) = canvas.lock() {
+        canvas.spawned_tasks += 1;
+        canvas.running_tasks += 1;
+        canvas.peak_running_tasks = canvas.peak_running_tasks.max(canvas.running_tasks);
+    }
+    let branches = branches.clone();
+    let canvas = canvas.clone();
+    thread_scope.spawn(move |s| {
+        let result = lay_colours_in_subtrees(s, branches, frames, fanout, canvas.clone());
+        if let Ok(mut canvas) = canvas.lock() {
+            canvas.running_tasks -= 1;
+            if let (Err(e), Ok(())) = (result, &canvas.task_result) {
+                canvas.task_result = Err(e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every pixel but the top left one is a child of the top left one
+    struct Star {
+        dimensions: (u32, u32),
+    }
+
+    impl Branches for Star {
+        fn dimensions(&self) -> (u32, u32) {
+            self.dimensions
+        }
+
+        fn for_each_child(
+            &self,
+            position: (u32, u32),
+            _: Neighbours,
+            mut child: impl FnMut(Neighbours, (u32, u32)),
+        ) -> Result<()> {
+            if position == (0, 0) {
+                let (height, width) = self.dimensions;
+                for index in 1..height * width {
+                    child(Neighbours::EAST, (index / width, index % width));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Paints every pixel but the root the same colour
+    #[derive(Clone, Copy)]
+    struct Paint(Rgb<u8>);
+
+    impl GenColour<Rgb<u8>> for Paint {
+        fn colour(&mut self, _: Rgb<u8>, _: Neighbours, _: (u32, u32), _: (u32, u32)) -> Rgb<u8> {
+            self.0
+        }
+
+        fn new(&mut self) -> Self {
+            *self
+        }
+    }
+
+    #[test]
+    fn star_spawns_at_most_max_fanout_tasks() {
+        let (height, width) = (1000, 1001);
+        let paint = Rgb([10, 200, 30]);
+        let star = Arc::new(Star {
+            dimensions: (height, width),
         });
+        for (split_at, max_fanout) in [(1, 1), (1, 8), (256, 8), (256, 100), (usize::MAX, 8)] {
+            let canvas = Arc::new(Mutex::new(Canvas::new(
+                ImageBuffer::new(width, height),
+                None,
+                ProgressBar::hidden(),
+                None,
+            )));
+            let root = Frame {
+                position: (0, 0),
+                visited_directions: Neighbours::empty(),
+                colour: paint,
+                colour_gen: Paint(paint),
+            };
+            let fanout = Fanout {
+                split_at,
+                max_fanout,
+            };
+            scope(|s| lay_colours_in_subtrees(s, star.clone(), vec![root], fanout, canvas.clone()))
+                .unwrap();
+            let canvas = canvas.lock().unwrap();
+            assert!(canvas.task_result.is_ok());
+            assert_eq!(canvas.running_tasks, 0);
+            // each task takes at least one of the root's shareable children
+            assert!(
+                canvas.spawned_tasks <= max_fanout,
+                "{} tasks splitting at {} with fanout {}",
+                canvas.spawned_tasks,
+                split_at,
+                max_fanout
+            );
+            assert!(canvas.peak_running_tasks <= canvas.spawned_tasks);
+            assert!(canvas.image.pixels().all(|&pixel| pixel == paint));
+        }
+    }
+
+    #[test]
+    fn fraction_to_pixel_covers_the_whole_axis() {
+        for size in [1, 3, 7, 9, 1001] {
+            assert_eq!(fraction_to_pixel(0., size), 0);
+            assert_eq!(fraction_to_pixel(1., size), size - 1);
+            assert_eq!(fraction_to_pixel(0.5, size), size / 2);
+            assert!(fraction_to_pixel(0.999, size) < size);
+        }
+        assert_eq!(fraction_to_pixel(0.25, 9), 2);
+        assert_eq!(fraction_to_pixel(0.75, 9), 6);
+        assert_eq!(fraction_to_pixel(0.2, 7), 1);
+        assert_eq!(fraction_to_pixel(0.999, 7), 6);
     }
-    Ok(())
 }
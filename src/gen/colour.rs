@@ -1,34 +1,389 @@
-use super::{trees::Neighbours, GenColour};
+use super::{trees::Neighbours, ColourChannel, ColourPixel, GenColour, WrapMode};
 use ::image::{Pixel, Rgb};
 use ::rand::prelude::Rng;
 use ::rand_xoshiro::Xoshiro128PlusPlus;
+use ::std::sync::Arc;
 
+/// Steps linearly through white, yellow, red and black
+///
+/// The cycle is designed around starting at black. Other start colours join
+/// it partway through, so the look of the output is only defined for black.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub(crate) struct TestGen;
 
+/// Any of the colour generators, chosen at runtime
+///
+/// Generators that only work on RGB colours leave any alpha channel as it was
+/// at the start pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AnyColour {
+    Test(TestGen),
+    Rand(RandColour),
+    Hsv(HsvRandColour),
+    Hue(HueDrift),
+    Gradient(DepthGradient),
+    Directional(DirectionalColour),
+    DirectionHue(DirectionHue),
+    Palette(PaletteColour),
+    Grey(GreyColour),
+    Radial(RadialColour),
+    Noise(NoiseColour),
+    Blend(BlendColour<RandColour, TestGen>),
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) struct RandColour {
+    /// Maximum displacement of the red, green, blue and alpha channels, in the
+    /// channel's own range
+    pub(crate) step_sizes: [u16; 4],
+    pub(crate) wrap_mode: WrapMode,
+    pub(crate) rng: Xoshiro128PlusPlus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HsvRandColour {
+    pub(crate) hue_step: f32,
+    pub(crate) sat_step: f32,
+    pub(crate) val_step: f32,
+    pub(crate) rng: Xoshiro128PlusPlus,
+}
+
+/// Rotates hue randomly while holding saturation and value within bounds
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HueDrift {
+    pub(crate) hue_step: f32,
+    pub(crate) sat_bounds: (f32, f32),
+    pub(crate) val_bounds: (f32, f32),
+    pub(crate) rng: Xoshiro128PlusPlus,
+}
+
+/// Fades between two colours according to distance from the root along the
+/// tree
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct DepthGradient {
+    pub(crate) from: Rgb<u8>,
+    pub(crate) to: Rgb<u8>,
+    /// Number of steps to fade over, after which the colour stays at `to`
+    pub(crate) length: u32,
+    /// Fade back and forth between the colours instead of stopping at `to`
+    pub(crate) bounce: bool,
+    /// Depth of the pixel this generator is colouring from
+    pub(crate) depth: u32,
+}
+
+/// Random walk where the direction of the fill pushes colour channels: moving
+/// north raises red and south lowers it, east raises green and west lowers it,
+/// and diagonals do half of each, while blue wanders freely
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct DirectionalColour {
+    pub(crate) step_size: u8,
+    pub(crate) rng: Xoshiro128PlusPlus,
+}
+
+/// Colours each pixel with a hue picked by the direction the fill moved in to
+/// reach it, so the image maps out the tree's growth: north is red, east is
+/// yellow-green, south is cyan, west is purple, and so on
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DirectionHue {
+    pub(crate) base_sat: f32,
+    pub(crate) base_val: f32,
+    /// How much the hue follows the direction rather than a random angle, in
+    /// 0..=1
+    pub(crate) blend_factor: f32,
+    pub(crate) rng: Xoshiro128PlusPlus,
+}
+
+/// Random walk confined to a palette, stepping each time to a random palette
+/// colour near the previous one
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PaletteColour {
+    /// Distinct colours to pick from, shared between generators
+    palette: Arc<[Rgb<u8>]>,
+    /// Indices of the colours each palette colour can step to, including
+    /// itself
+    neighbours: Arc<[Vec<usize>]>,
+    rng: Xoshiro128PlusPlus,
+}
+
+/// Random walk in every channel whose brightness is held down further from
+/// the centre of the image, darkening towards the edges like a vignette
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RadialColour {
+    pub(crate) step_size: u8,
+    /// Where brightness is unlimited, as fractions across and down the image
+    pub(crate) centre: (f64, f64),
+    pub(crate) rng: Xoshiro128PlusPlus,
+}
+
+/// Reads each channel from fractal noise, moving further through it with each
+/// step away from the root
+///
+/// The position in noise space is one-dimensional, so nothing is stored per
+/// pixel. Each channel has its own offset into the noise, and every branch
+/// shifts the offsets so siblings drift apart.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct NoiseColour {
+    /// Distance moved through the noise per step
+    pub(crate) scale: f64,
+    pub(crate) octaves: u32,
+    /// How much each octave is scaled down compared to the one before
+    pub(crate) persistence: f64,
+    pub(crate) offset_r: f64,
+    pub(crate) offset_g: f64,
+    pub(crate) offset_b: f64,
+    pub(crate) depth: u32,
+}
+
+/// Mixes the colours picked by two generators from the same parent colour,
+/// from all `a` at a `blend` of 0 to all `b` at 1
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BlendColour<A, B> {
+    pub(crate) a: A,
+    pub(crate) b: B,
+    pub(crate) blend: f32,
+}
+
+/// Random walk in brightness alone, giving shades of grey
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct GreyColour {
     pub(crate) step_size: u8,
     pub(crate) rng: Xoshiro128PlusPlus,
 }
 
 impl RandColour {
-    pub(crate) fn rand_channel(&mut self, old: u8, step_size: u8) -> u8 {
-        let max = old.saturating_add(step_size);
-        let min = old.saturating_sub(step_size);
-        self.rng.gen_range(min..max)
+    /// Move a channel by up to `step_size` either way, with every offset
+    /// equally likely
+    pub(crate) fn rand_channel<C: ColourChannel>(&mut self, old: C, step_size: u16) -> C {
+        // a channel with no room to move never changes
+        if step_size == 0 {
+            return old;
+        }
+        let step_size = i32::from(step_size);
+        let offset = self.rng.gen_range(-step_size..=step_size);
+        match self.wrap_mode {
+            WrapMode::Saturate => {
+                // the offset is at most a u16 step size either way
+                let distance = C::from_step(offset.unsigned_abs() as u16);
+                if offset < 0 {
+                    old.saturating_sub(distance)
+                } else {
+                    old.saturating_add(distance)
+                }
+            }
+            WrapMode::Wrap => old.wrapping_offset(offset),
+            WrapMode::Mirror => old.mirroring_offset(offset),
+        }
+    }
+}
+
+impl PaletteColour {
+    /// Join each palette colour to the others within `step_size` of it on
+    /// every channel
+    ///
+    /// Groups of colours further apart than that are joined along the
+    /// palette's minimum spanning tree, so the walk can reach every colour
+    /// while keeping its steps as short as possible.
+    pub(crate) fn from_palette(
+        palette: &[Rgb<u8>],
+        step_size: u8,
+        rng: Xoshiro128PlusPlus,
+    ) -> PaletteColour {
+        let mut neighbours = palette
+            .iter()
+            .map(|&Rgb(colour)| {
+                palette
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &Rgb(other))| {
+                        colour
+                            .iter()
+                            .zip(other)
+                            .all(|(&a, b)| a.abs_diff(b) <= step_size)
+                    })
+                    .map(|(other, _)| other)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // Prim's algorithm, tracking the closest colour in the tree to each
+        // colour outside it
+        let mut closest = vec![(u32::MAX, 0); palette.len()];
+        let mut in_tree = vec![false; palette.len()];
+        let mut newest = 0;
+        for _ in 1..palette.len() {
+            in_tree[newest] = true;
+            for (other, closest) in closest.iter_mut().enumerate() {
+                let distance = distance(palette[newest], palette[other]);
+                if !in_tree[other] && distance < closest.0 {
+                    *closest = (distance, newest);
+                }
+            }
+            let Some(next) = (0..palette.len())
+                .filter(|&other| !in_tree[other])
+                .min_by_key(|&other| closest[other].0)
+            else {
+                break;
+            };
+            let parent = closest[next].1;
+            if !neighbours[next].contains(&parent) {
+                neighbours[next].push(parent);
+                neighbours[parent].push(next);
+            }
+            newest = next;
+        }
+        PaletteColour {
+            palette: palette.into(),
+            neighbours: neighbours.into(),
+            rng,
+        }
+    }
+
+    /// Index of the palette colour closest to a colour
+    fn nearest_index(&self, colour: Rgb<u8>) -> Option<usize> {
+        (0..self.palette.len()).min_by_key(|&index| distance(colour, self.palette[index]))
+    }
+
+    /// The palette colour closest to a colour by Euclidean distance in RGB
+    pub(crate) fn nearest(&self, colour: Rgb<u8>) -> Rgb<u8> {
+        self.nearest_index(colour)
+            .map_or(colour, |index| self.palette[index])
+    }
+}
+
+/// Squared Euclidean distance between two colours in RGB
+fn distance(Rgb(a): Rgb<u8>, Rgb(b): Rgb<u8>) -> u32 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, b)| u32::from(a.abs_diff(b)).pow(2))
+        .sum()
+}
+
+/// Convert a colour into hue in degrees, saturation and value in 0..=1
+pub(crate) fn rgb_to_hsv(Rgb([r, g, b]): Rgb<u8>) -> (f32, f32, f32) {
+    let (r, g, b) = (
+        f32::from(r) / 255.,
+        f32::from(g) / 255.,
+        f32::from(b) / 255.,
+    );
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let hue = if chroma == 0. {
+        0.
+    } else if max == r {
+        60. * ((g - b) / chroma).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / chroma + 2.)
+    } else {
+        60. * ((r - g) / chroma + 4.)
+    };
+    let sat = if max == 0. { 0. } else { chroma / max };
+    (hue, sat, max)
+}
+
+/// Convert hue in degrees, saturation and value in 0..=1 into a colour
+pub(crate) fn hsv_to_rgb(hue: f32, sat: f32, val: f32) -> Rgb<u8> {
+    let chroma = val * sat;
+    let sector = hue.rem_euclid(360.) / 60.;
+    let x = chroma * (1. - (sector.rem_euclid(2.) - 1.).abs());
+    let (r, g, b) = match sector as u8 {
+        0 => (chroma, x, 0.),
+        1 => (x, chroma, 0.),
+        2 => (0., chroma, x),
+        3 => (0., x, chroma),
+        4 => (x, 0., chroma),
+        _ => (chroma, 0., x),
+    };
+    let min = val - chroma;
+    let channel = |c: f32| ((c + min) * 255.).round().clamp(0., 255.) as u8;
+    Rgb([channel(r), channel(g), channel(b)])
+}
+
+/// Convert an sRGB channel to linear light in 0..=1
+fn srgb_to_linear(channel: u8) -> f32 {
+    let c = f32::from(channel) / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert linear light in 0..=1 to an sRGB channel
+fn linear_to_srgb(linear: f32) -> u8 {
+    let c = if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1. / 2.4) - 0.055
+    };
+    (c * 255.).round().clamp(0., 255.) as u8
+}
+
+/// Interpolate between two colours in linear light, with `t` in 0..=1
+pub(crate) fn lerp_colour(Rgb(from): Rgb<u8>, Rgb(to): Rgb<u8>, t: f32) -> Rgb<u8> {
+    let channel = |i: usize| {
+        let (a, b) = (srgb_to_linear(from[i]), srgb_to_linear(to[i]));
+        linear_to_srgb(a + (b - a) * t)
+    };
+    Rgb([channel(0), channel(1), channel(2)])
+}
+
+impl DepthGradient {
+    /// The colour of a pixel at a given depth
+    pub(crate) fn colour_at(&self, depth: u32) -> Rgb<u8> {
+        let length = self.length.max(1);
+        let depth = if self.bounce {
+            let period = 2 * u64::from(length);
+            let phase = u64::from(depth) % period;
+            phase.min(period - phase) as u32
+        } else {
+            depth.min(length)
+        };
+        let t = depth as f32 / length as f32;
+        lerp_colour(self.from, self.to, t)
     }
 }
 
-impl GenColour for TestGen {
-    fn colour(&mut self, old_colour: Rgb<u8>, _: Neighbours) -> Rgb<u8> {
-        *Pixel::from_slice(&match old_colour.channels() {
-            &[255, 255, 255] => [0, 0, 0],
-            &[255, 255, b] => [255, 255, b + 1],
-            &[255, g, b] => [255, g + 1, b],
-            &[r, g, b] => [r + 1, g, b],
-            _ => return old_colour,
-        })
+impl DirectionalColour {
+    /// How strongly a direction pushes the red and green channels, in -1..=1
+    pub(crate) fn bias(direction: Neighbours) -> (f32, f32) {
+        let vertical = if Neighbours::NORTHWARD.contains(direction) {
+            1.
+        } else if Neighbours::SOUTHWARD.contains(direction) {
+            -1.
+        } else {
+            0.
+        };
+        let horizontal = if Neighbours::EASTWARD.contains(direction) {
+            1.
+        } else if Neighbours::WESTWARD.contains(direction) {
+            -1.
+        } else {
+            0.
+        };
+        if vertical != 0. && horizontal != 0. {
+            (vertical / 2., horizontal / 2.)
+        } else {
+            (vertical, horizontal)
+        }
+    }
+
+    /// Move a channel in the direction of its bias by a random amount up to the
+    /// step size
+    fn push_channel(&mut self, old: u8, bias: f32) -> u8 {
+        let distance = f32::from(self.rng.gen_range(0..=self.step_size));
+        (f32::from(old) + bias * distance).round().clamp(0., 255.) as u8
+    }
+}
+
+impl<P: ColourPixel> GenColour<P> for TestGen {
+    /// Leaves any alpha channel unchanged
+    fn colour(&mut self, old_colour: P, _: Neighbours, _: (u32, u32), _: (u32, u32)) -> P {
+        old_colour.with_rgb(Rgb(match old_colour.rgb().0 {
+            [255, 255, 255] => [0, 0, 0],
+            [255, 255, b] => [255, 255, b + 1],
+            [255, g, b] => [255, g + 1, b],
+            [r, g, b] => [r + 1, g, b],
+        }))
     }
 
     fn new(&mut self) -> Self {
@@ -36,17 +391,14 @@ impl GenColour for TestGen {
     }
 }
 
-impl GenColour for RandColour {
-    fn colour(&mut self, old_colour: Rgb<u8>, _: Neighbours) -> Rgb<u8> {
-        if let &[r, g, b] = old_colour.channels() {
-            *Pixel::from_slice(&[
-                self.rand_channel(r, self.step_size),
-                self.rand_channel(g, self.step_size),
-                self.rand_channel(b, self.step_size),
-            ])
-        } else {
-            old_colour
+impl<P: ColourPixel> GenColour<P> for RandColour {
+    /// Perturbs every channel, including any alpha channel
+    fn colour(&mut self, mut old_colour: P, _: Neighbours, _: (u32, u32), _: (u32, u32)) -> P {
+        let step_sizes = self.step_sizes;
+        for (channel, &step_size) in old_colour.channels_mut().iter_mut().zip(&step_sizes) {
+            *channel = self.rand_channel(*channel, step_size);
         }
+        old_colour
     }
 
     fn new(&mut self) -> Self {
@@ -54,8 +406,643 @@ impl GenColour for RandColour {
         self.rng.long_jump();
         rng.jump();
         RandColour {
+            step_sizes: self.step_sizes,
+            wrap_mode: self.wrap_mode,
+            rng,
+        }
+    }
+}
+
+impl GenColour<Rgb<u8>> for HsvRandColour {
+    fn colour(
+        &mut self,
+        old_colour: Rgb<u8>,
+        _: Neighbours,
+        _: (u32, u32),
+        _: (u32, u32),
+    ) -> Rgb<u8> {
+        let (hue, sat, val) = rgb_to_hsv(old_colour);
+        let hue = (hue + self.rng.gen_range(-self.hue_step..=self.hue_step)).rem_euclid(360.);
+        let sat = (sat + self.rng.gen_range(-self.sat_step..=self.sat_step)).clamp(0., 1.);
+        let val = (val + self.rng.gen_range(-self.val_step..=self.val_step)).clamp(0., 1.);
+        hsv_to_rgb(hue, sat, val)
+    }
+
+    fn new(&mut self) -> Self {
+        let mut rng = self.rng.clone();
+        self.rng.long_jump();
+        rng.jump();
+        HsvRandColour { rng, ..*self }
+    }
+}
+
+impl GenColour<Rgb<u8>> for HueDrift {
+    fn colour(
+        &mut self,
+        old_colour: Rgb<u8>,
+        _: Neighbours,
+        _: (u32, u32),
+        _: (u32, u32),
+    ) -> Rgb<u8> {
+        let (hue, sat, val) = rgb_to_hsv(old_colour);
+        // hue is an angle, so it wraps rather than clamping
+        let hue = (hue + self.rng.gen_range(-self.hue_step..=self.hue_step)).rem_euclid(360.);
+        let sat = sat.clamp(self.sat_bounds.0, self.sat_bounds.1);
+        let val = val.clamp(self.val_bounds.0, self.val_bounds.1);
+        hsv_to_rgb(hue, sat, val)
+    }
+
+    fn new(&mut self) -> Self {
+        let mut rng = self.rng.clone();
+        self.rng.long_jump();
+        rng.jump();
+        HueDrift { rng, ..*self }
+    }
+}
+
+impl GenColour<Rgb<u8>> for DepthGradient {
+    fn colour(&mut self, _: Rgb<u8>, _: Neighbours, _: (u32, u32), _: (u32, u32)) -> Rgb<u8> {
+        self.colour_at(self.depth.saturating_add(1))
+    }
+
+    /// Children are one step deeper, so siblings share the same colour
+    fn new(&mut self) -> Self {
+        DepthGradient {
+            depth: self.depth.saturating_add(1),
+            ..*self
+        }
+    }
+}
+
+impl DirectionHue {
+    /// Hue in degrees for a single direction, going clockwise from north at 0
+    ///
+    /// Returns `None` unless exactly one direction is given.
+    pub(crate) fn base_hue(direction: Neighbours) -> Option<f32> {
+        (direction.count_set() == 1).then(|| direction.bits().trailing_zeros() as f32 * 45.)
+    }
+}
+
+impl GenColour<Rgb<u8>> for DirectionHue {
+    fn colour(
+        &mut self,
+        _: Rgb<u8>,
+        direction_into: Neighbours,
+        _: (u32, u32),
+        _: (u32, u32),
+    ) -> Rgb<u8> {
+        match DirectionHue::base_hue(direction_into) {
+            Some(base_hue) => {
+                let offset = self.rng.gen_range(-180f32..=180.) * (1. - self.blend_factor);
+                hsv_to_rgb(
+                    (base_hue + offset).rem_euclid(360.),
+                    self.base_sat,
+                    self.base_val,
+                )
+            }
+            // No direction, such as at a root, so use a neutral grey
+            None => hsv_to_rgb(0., 0., self.base_val),
+        }
+    }
+
+    fn new(&mut self) -> Self {
+        let mut rng = self.rng.clone();
+        self.rng.long_jump();
+        rng.jump();
+        DirectionHue { rng, ..*self }
+    }
+}
+
+impl GenColour<Rgb<u8>> for DirectionalColour {
+    fn colour(
+        &mut self,
+        Rgb([r, g, b]): Rgb<u8>,
+        direction_into: Neighbours,
+        _: (u32, u32),
+        _: (u32, u32),
+    ) -> Rgb<u8> {
+        let (red_bias, green_bias) = DirectionalColour::bias(direction_into);
+        let blue_shift =
+            i16::from(self.rng.gen_range(0..=self.step_size)) * if self.rng.gen() { 1 } else { -1 };
+        Rgb([
+            self.push_channel(r, red_bias),
+            self.push_channel(g, green_bias),
+            (i16::from(b) + blue_shift).clamp(0, 255) as u8,
+        ])
+    }
+
+    fn new(&mut self) -> Self {
+        let mut rng = self.rng.clone();
+        self.rng.long_jump();
+        rng.jump();
+        DirectionalColour {
+            step_size: self.step_size,
+            rng,
+        }
+    }
+}
+
+impl GenColour<Rgb<u8>> for PaletteColour {
+    fn colour(
+        &mut self,
+        old_colour: Rgb<u8>,
+        _: Neighbours,
+        _: (u32, u32),
+        _: (u32, u32),
+    ) -> Rgb<u8> {
+        match self.nearest_index(old_colour) {
+            Some(index) => {
+                let near = &self.neighbours[index];
+                self.palette[near[self.rng.gen_range(0..near.len())]]
+            }
+            None => old_colour,
+        }
+    }
+
+    fn new(&mut self) -> Self {
+        let mut rng = self.rng.clone();
+        self.rng.long_jump();
+        rng.jump();
+        PaletteColour {
+            palette: self.palette.clone(),
+            neighbours: self.neighbours.clone(),
+            rng,
+        }
+    }
+}
+
+impl GenColour<Rgb<u8>> for GreyColour {
+    /// Bounces off black and white rather than stopping at them, so long
+    /// branches don't all end up the same shade
+    fn colour(
+        &mut self,
+        old_colour: Rgb<u8>,
+        _: Neighbours,
+        _: (u32, u32),
+        _: (u32, u32),
+    ) -> Rgb<u8> {
+        let step_size = i32::from(self.step_size);
+        let [luma] = old_colour.to_luma().0;
+        let luma = luma.mirroring_offset(self.rng.gen_range(-step_size..=step_size));
+        Rgb([luma; 3])
+    }
+
+    fn new(&mut self) -> Self {
+        let mut rng = self.rng.clone();
+        self.rng.long_jump();
+        rng.jump();
+        GreyColour {
             step_size: self.step_size,
             rng,
         }
     }
 }
+
+impl RadialColour {
+    /// How far a pixel is from the centre, from 0 there to 1 at the farthest
+    /// corner
+    fn distance((row, col): (u32, u32), (height, width): (u32, u32), (x, y): (f64, f64)) -> f32 {
+        // the same pixel the fill starts from
+        let centre_row = (y * f64::from(height))
+            .floor()
+            .min(f64::from(height.saturating_sub(1)));
+        let centre_col = (x * f64::from(width))
+            .floor()
+            .min(f64::from(width.saturating_sub(1)));
+        let farthest_row = centre_row.max(f64::from(height.saturating_sub(1)) - centre_row);
+        let farthest_col = centre_col.max(f64::from(width.saturating_sub(1)) - centre_col);
+        let farthest = farthest_row.hypot(farthest_col);
+        if farthest == 0. {
+            return 0.;
+        }
+        let distance = (f64::from(row) - centre_row).hypot(f64::from(col) - centre_col);
+        (distance / farthest) as f32
+    }
+}
+
+impl GenColour<Rgb<u8>> for RadialColour {
+    /// Pulls value part of the way back down whenever it rises above one minus
+    /// the distance from the centre
+    fn colour(
+        &mut self,
+        old_colour: Rgb<u8>,
+        _: Neighbours,
+        position: (u32, u32),
+        dimensions: (u32, u32),
+    ) -> Rgb<u8> {
+        let step = i16::from(self.step_size);
+        let walked = old_colour.map(|channel| {
+            (i16::from(channel) + self.rng.gen_range(-step..=step)).clamp(0, 255) as u8
+        });
+        let limit = 1. - Self::distance(position, dimensions, self.centre);
+        let (hue, sat, val) = rgb_to_hsv(walked);
+        if val <= limit {
+            return walked;
+        }
+        hsv_to_rgb(hue, sat, val - (val - limit) * RADIAL_PULL)
+    }
+
+    fn new(&mut self) -> Self {
+        let mut rng = self.rng.clone();
+        self.rng.long_jump();
+        rng.jump();
+        RadialColour {
+            step_size: self.step_size,
+            centre: self.centre,
+            rng,
+        }
+    }
+}
+
+/// How much of the excess brightness the radial generator removes each step
+const RADIAL_PULL: f32 = 0.25;
+
+impl NoiseColour {
+    /// The colour of a pixel at a given depth along this branch
+    pub(crate) fn colour_at(&self, depth: u32) -> Rgb<u8> {
+        let along = f64::from(depth) * self.scale;
+        let channel = |offset: f64| {
+            let noise = fbm(along + offset, self.octaves, self.persistence);
+            ((noise + 1.) / 2. * 255.).round().clamp(0., 255.) as u8
+        };
+        Rgb([
+            channel(self.offset_r),
+            channel(self.offset_g),
+            channel(self.offset_b),
+        ])
+    }
+}
+
+impl GenColour<Rgb<u8>> for NoiseColour {
+    fn colour(&mut self, _: Rgb<u8>, _: Neighbours, _: (u32, u32), _: (u32, u32)) -> Rgb<u8> {
+        self.colour_at(self.depth.saturating_add(1))
+    }
+
+    /// Children are one step deeper, with offsets a step further on than
+    /// the last sibling's, so siblings start close together and drift apart
+    fn new(&mut self) -> Self {
+        self.offset_r += self.scale;
+        self.offset_g += self.scale;
+        self.offset_b += self.scale;
+        NoiseColour {
+            depth: self.depth.saturating_add(1),
+            ..*self
+        }
+    }
+}
+
+/// Fractal Brownian motion: octaves of gradient noise at doubling
+/// frequencies, from -1 to 1
+fn fbm(x: f64, octaves: u32, persistence: f64) -> f64 {
+    let mut total = 0.;
+    let mut amplitude = 1.;
+    let mut max = 0.;
+    let mut frequency = 1.;
+    for _ in 0..octaves.max(1) {
+        total += gradient_noise(x * frequency) * amplitude;
+        max += amplitude;
+        amplitude *= persistence;
+        frequency *= 2.;
+    }
+    if max > 0. {
+        (total / max).clamp(-1., 1.)
+    } else {
+        0.
+    }
+}
+
+/// One-dimensional Perlin noise, from -1 to 1
+fn gradient_noise(x: f64) -> f64 {
+    let cell = x.floor();
+    let t = x - cell;
+    let gradient = |cell: f64| {
+        // SplitMix64's finaliser, to scatter neighbouring cells
+        let mut hash = (cell as i64 as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        hash ^= hash >> 31;
+        (hash >> 11) as f64 / (1u64 << 52) as f64 - 1.
+    };
+    let fade = t * t * t * (t * (t * 6. - 15.) + 10.);
+    let left = gradient(cell) * t;
+    let right = gradient(cell + 1.) * (t - 1.);
+    // a gradient of 1 peaks at 0.5 halfway between cells
+    (left + (right - left) * fade) * 2.
+}
+
+impl<P: ColourPixel, A: GenColour<P>, B: GenColour<P>> GenColour<P> for BlendColour<A, B> {
+    /// Interpolates every channel, including any alpha channel
+    fn colour(
+        &mut self,
+        old_colour: P,
+        direction_into: Neighbours,
+        position: (u32, u32),
+        dimensions: (u32, u32),
+    ) -> P {
+        let a = self
+            .a
+            .colour(old_colour, direction_into, position, dimensions);
+        let b = self
+            .b
+            .colour(old_colour, direction_into, position, dimensions);
+        a.map2(&b, |a, b| a.lerp(b, self.blend))
+    }
+
+    fn new(&mut self) -> Self {
+        BlendColour {
+            a: self.a.new(),
+            b: self.b.new(),
+            blend: self.blend,
+        }
+    }
+}
+
+impl<P: ColourPixel> GenColour<P> for AnyColour {
+    fn colour(
+        &mut self,
+        old_colour: P,
+        direction_into: Neighbours,
+        position: (u32, u32),
+        dimensions: (u32, u32),
+    ) -> P {
+        let rgb = old_colour.rgb();
+        match self {
+            AnyColour::Test(gen) => gen.colour(old_colour, direction_into, position, dimensions),
+            AnyColour::Rand(gen) => gen.colour(old_colour, direction_into, position, dimensions),
+            AnyColour::Hsv(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Hue(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Gradient(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Directional(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::DirectionHue(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Palette(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Grey(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Radial(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Noise(gen) => {
+                old_colour.with_rgb(gen.colour(rgb, direction_into, position, dimensions))
+            }
+            AnyColour::Blend(gen) => gen.colour(old_colour, direction_into, position, dimensions),
+        }
+    }
+
+    fn new(&mut self) -> Self {
+        match self {
+            AnyColour::Test(gen) => AnyColour::Test(GenColour::<P>::new(gen)),
+            AnyColour::Rand(gen) => AnyColour::Rand(GenColour::<P>::new(gen)),
+            AnyColour::Hsv(gen) => AnyColour::Hsv(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Hue(gen) => AnyColour::Hue(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Gradient(gen) => AnyColour::Gradient(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Directional(gen) => AnyColour::Directional(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::DirectionHue(gen) => AnyColour::DirectionHue(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Palette(gen) => AnyColour::Palette(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Grey(gen) => AnyColour::Grey(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Radial(gen) => AnyColour::Radial(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Noise(gen) => AnyColour::Noise(GenColour::<Rgb<u8>>::new(gen)),
+            AnyColour::Blend(gen) => AnyColour::Blend(GenColour::<P>::new(gen)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::rand::SeedableRng;
+
+    fn rand_colour(seed: u64) -> RandColour {
+        RandColour {
+            step_sizes: [10; 4],
+            wrap_mode: WrapMode::Mirror,
+            rng: Xoshiro128PlusPlus::seed_from_u64(seed),
+        }
+    }
+
+    /// Colours from walking a generator and the generators split off it
+    fn walk<G: GenColour<Rgb<u8>>>(mut gen: G) -> Vec<Rgb<u8>> {
+        let mut colour = Rgb([128, 64, 192]);
+        let mut colours = Vec::new();
+        for _ in 0..3 {
+            let mut branch = gen.new();
+            for direction in Neighbours::DIRECTIONS {
+                colour = gen.colour(colour, direction, (0, 0), (1, 1));
+                colours.push(colour);
+                colours.push(branch.colour(colour, direction, (0, 0), (1, 1)));
+            }
+        }
+        colours
+    }
+
+    #[test]
+    fn blend_ends_match_either_generator() {
+        let blend = |blend| BlendColour {
+            a: rand_colour(7),
+            b: rand_colour(8),
+            blend,
+        };
+        assert_eq!(walk(blend(0.)), walk(rand_colour(7)));
+        assert_eq!(walk(blend(1.)), walk(rand_colour(8)));
+        let test_blend = BlendColour {
+            a: rand_colour(7),
+            b: TestGen,
+            blend: 1.,
+        };
+        assert_eq!(walk(test_blend), walk(TestGen));
+    }
+
+    #[test]
+    fn radial_darkens_corners() {
+        let dimensions = (101, 101);
+        let mut gen = RadialColour {
+            step_size: 10,
+            centre: (0.5, 0.5),
+            rng: Xoshiro128PlusPlus::seed_from_u64(3),
+        };
+        let mut mean_value = |position| {
+            let mut colour = Rgb([255; 3]);
+            let mut total = 0.;
+            for _ in 0..1000 {
+                colour = gen.colour(colour, Neighbours::NORTH, position, dimensions);
+                total += rgb_to_hsv(colour).2;
+            }
+            total / 1000.
+        };
+        let centre = mean_value((50, 50));
+        for corner in [(0, 0), (0, 100), (100, 0), (100, 100)] {
+            let corner = mean_value(corner);
+            assert!(
+                corner < centre / 2.,
+                "corner {} vs centre {}",
+                corner,
+                centre
+            );
+        }
+    }
+
+    const STEP_SIZES: [u16; 6] = [0, 1, 5, 10, 128, 300];
+    const WRAP_MODES: [WrapMode; 3] = [WrapMode::Saturate, WrapMode::Wrap, WrapMode::Mirror];
+
+    #[test]
+    fn rand_channel_never_panics_or_leaves_bounds() {
+        for wrap_mode in WRAP_MODES {
+            let mut gen = RandColour {
+                wrap_mode,
+                ..rand_colour(1)
+            };
+            for step_size in STEP_SIZES {
+                for old in 0..=u8::MAX {
+                    for _ in 0..20 {
+                        let new = gen.rand_channel(old, step_size);
+                        let mut distance = u16::from(new.abs_diff(old));
+                        if wrap_mode == WrapMode::Wrap {
+                            distance = distance.min(256 - distance);
+                        }
+                        assert!(
+                            distance <= step_size,
+                            "{:?} moved {} to {} with step {}",
+                            wrap_mode,
+                            old,
+                            new,
+                            step_size
+                        );
+                    }
+                    // the same steps on a 16-bit channel
+                    gen.rand_channel(u16::from(old) * 257, step_size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rand_channel_is_unbiased_away_from_bounds() {
+        const SAMPLES: i32 = 4000;
+        for wrap_mode in WRAP_MODES {
+            let mut gen = RandColour {
+                wrap_mode,
+                ..rand_colour(2)
+            };
+            for step_size in STEP_SIZES.into_iter().filter(|&step| step <= 100) {
+                let step = i32::from(step_size);
+                for old in step..=i32::from(u8::MAX) - step {
+                    let (mut total, mut lowest, mut highest) = (0, 0, 0);
+                    for _ in 0..SAMPLES {
+                        let offset = i32::from(gen.rand_channel(old as u8, step_size)) - old;
+                        total += offset;
+                        lowest = lowest.min(offset);
+                        highest = highest.max(offset);
+                    }
+                    // offsets are uniform over -step..=step, so their mean
+                    // has a standard deviation of about step / sqrt(3 * SAMPLES)
+                    let mean = f64::from(total) / f64::from(SAMPLES);
+                    let tolerance = 5. * f64::from(step) / f64::from(3 * SAMPLES).sqrt();
+                    assert!(
+                        mean.abs() <= tolerance,
+                        "{:?} drifted {} from {} with step {}",
+                        wrap_mode,
+                        mean,
+                        old,
+                        step_size
+                    );
+                    assert_eq!((lowest, highest), (-step, step));
+                }
+            }
+        }
+    }
+
+    fn gradient(length: u32, bounce: bool) -> DepthGradient {
+        DepthGradient {
+            from: Rgb([0, 100, 200]),
+            to: Rgb([200, 100, 0]),
+            length,
+            bounce,
+            depth: 0,
+        }
+    }
+
+    #[test]
+    fn depth_gradient_fades_from_one_colour_to_the_other() {
+        for bounce in [false, true] {
+            let gradient = gradient(10, bounce);
+            assert_eq!(gradient.colour_at(0), gradient.from);
+            let halfway = lerp_colour(gradient.from, gradient.to, 0.5);
+            assert_eq!(gradient.colour_at(5), halfway);
+            assert_eq!(gradient.colour_at(10), gradient.to);
+        }
+        assert_eq!(gradient(10, false).colour_at(25), Rgb([200, 100, 0]));
+    }
+
+    #[test]
+    fn depth_gradient_bounces_with_a_period_of_twice_its_length() {
+        let gradient = gradient(10, true);
+        for depth in 0..40 {
+            assert_eq!(gradient.colour_at(depth), gradient.colour_at(depth + 20));
+            assert_eq!(
+                gradient.colour_at(10 + depth % 10),
+                gradient.colour_at(10 - depth % 10)
+            );
+        }
+        assert_eq!(gradient.colour_at(15), gradient.colour_at(5));
+        assert_eq!(gradient.colour_at(20), gradient.from);
+    }
+
+    #[test]
+    fn palette_joins_far_apart_groups() {
+        let palette = [
+            Rgb([0, 0, 0]),
+            Rgb([5, 5, 5]),
+            Rgb([250, 250, 250]),
+            Rgb([245, 250, 255]),
+            Rgb([0, 255, 0]),
+        ];
+        let gen = PaletteColour::from_palette(&palette, 10, Xoshiro128PlusPlus::seed_from_u64(0));
+        let mut reached = vec![false; palette.len()];
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            if !::std::mem::replace(&mut reached[index], true) {
+                stack.extend(&gen.neighbours[index]);
+            }
+        }
+        assert!(reached.into_iter().all(|reached| reached));
+        for (index, near) in gen.neighbours.iter().enumerate() {
+            assert!(near.contains(&index), "{} can't stay put", index);
+            for &other in near {
+                assert!(gen.neighbours[other].contains(&index));
+            }
+        }
+        // the close pairs are joined without going through the MST
+        assert!(gen.neighbours[0].contains(&1));
+        assert!(gen.neighbours[2].contains(&3));
+        assert!(walk(gen).iter().all(|colour| palette.contains(colour)));
+    }
+
+    #[test]
+    fn directional_bias_follows_each_direction() {
+        let expected = [
+            (Neighbours::NORTH, (1., 0.)),
+            (Neighbours::NORTHEAST, (0.5, 0.5)),
+            (Neighbours::EAST, (0., 1.)),
+            (Neighbours::SOUTHEAST, (-0.5, 0.5)),
+            (Neighbours::SOUTH, (-1., 0.)),
+            (Neighbours::SOUTHWEST, (-0.5, -0.5)),
+            (Neighbours::WEST, (0., -1.)),
+            (Neighbours::NORTHWEST, (0.5, -0.5)),
+        ];
+        assert_eq!(expected.len(), Neighbours::DIRECTIONS.len());
+        for (direction, bias) in expected {
+            assert_eq!(DirectionalColour::bias(direction), bias, "{:?}", direction);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use super::{trees::UnionFind, Neighbours};
+use ::anyhow::{ensure, Context, Result};
+use ::std::path::Path;
+
+/// Read a mask image, giving whether each pixel is kept in row-major order
+///
+/// Colour masks are converted to greyscale, and pixels at least as bright as
+/// the threshold are kept.
+pub(crate) fn load(path: &Path, (width, height): (u32, u32), threshold: u8) -> Result<Vec<bool>> {
+    let mask = ::image::open(path)
+        .with_context(|| format!("Failed to read mask {}", path.display()))?
+        .into_luma8();
+    ensure!(
+        mask.dimensions() == (width, height),
+        "Mask {} is {}x{}, but the image is {}x{}",
+        path.display(),
+        mask.width(),
+        mask.height(),
+        width,
+        height
+    );
+    Ok(mask.pixels().map(|pixel| pixel.0[0] >= threshold).collect())
+}
+
+/// Cut every edge touching a masked-out pixel, then join up what's left of the
+/// tree inside the mask
+///
+/// Cutting a tree leaves it in pieces wherever a path between two kept pixels
+/// went outside the mask. The pieces are joined along any edge the topology
+/// allows, so every kept pixel connected to a start point through the mask
+/// gets coloured.
+pub(crate) fn apply(
+    tree: &mut [Neighbours],
+    mask: &[bool],
+    (width, height): (usize, usize),
+    possible_edges: &[Neighbours],
+) -> Result<()> {
+    let endpoint = |index: usize, direction: Neighbours| {
+        let (row, col) =
+            direction.step_wrapping_usize((index / width, index % width), (height, width));
+        row * width + col
+    };
+    let mut components = UnionFind::new(tree.len());
+    for index in 0..tree.len() {
+        let point = tree[index];
+        for direction in point {
+            let end = endpoint(index, direction);
+            if mask[index] && mask[end] {
+                components.union(index, end);
+            } else {
+                tree[index].remove(direction);
+            }
+        }
+    }
+    for (index, &edges) in possible_edges.iter().enumerate() {
+        if !mask[index] {
+            continue;
+        }
+        for direction in edges {
+            let end = endpoint(index, direction);
+            if mask[end] && components.union(index, end) {
+                tree[index] |= direction;
+                tree[end] |= direction
+                    .reverse()
+                    .context("Couldn't calculate reverse of direction to a point")?;
+            }
+        }
+    }
+    Ok(())
+}
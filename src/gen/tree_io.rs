@@ -0,0 +1,174 @@
+use super::{trees::UnionFind, Neighbours};
+use ::anyhow::{bail, ensure, Context, Result};
+use ::std::{fs, path::Path};
+
+/// Start of every tree file
+const MAGIC: &[u8; 8] = b"LAPTREE\0";
+/// Format version, bumped whenever the layout changes
+const VERSION: u8 = 1;
+/// Magic bytes, version, then width and height as little-endian u32s
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + 4;
+
+/// Write a tree to a file, one byte of neighbour flags per pixel after a
+/// header giving the dimensions
+pub(crate) fn save(path: &Path, (width, height): (u32, u32), tree: &[Neighbours]) -> Result<()> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + tree.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes.extend_from_slice(&height.to_le_bytes());
+    bytes.extend(tree.iter().map(|point| point.bits()));
+    fs::write(path, bytes).with_context(|| format!("Failed to write tree {}", path.display()))
+}
+
+/// Read a tree written by [`save`] for an image with the given dimensions
+///
+/// The tree is checked to be a forest whose edges are recorded at both ends,
+/// since colouring would never finish on a tree with a cycle.
+pub(crate) fn load(path: &Path, (width, height): (u32, u32)) -> Result<Vec<Neighbours>> {
+    let bytes =
+        fs::read(path).with_context(|| format!("Failed to read tree {}", path.display()))?;
+    parse(&bytes, (width, height)).with_context(|| format!("Invalid tree {}", path.display()))
+}
+
+fn parse(bytes: &[u8], (width, height): (u32, u32)) -> Result<Vec<Neighbours>> {
+    ensure!(
+        bytes.len() >= HEADER_LEN && bytes.starts_with(MAGIC),
+        "Not a tree file"
+    );
+    let (header, points) = bytes.split_at(HEADER_LEN);
+    let version = header[MAGIC.len()];
+    ensure!(
+        version == VERSION,
+        "Tree file is version {}, but only version {} can be read",
+        version,
+        VERSION
+    );
+    let dimension = |offset: usize| {
+        let start = MAGIC.len() + 1 + offset;
+        u32::from_le_bytes([
+            header[start],
+            header[start + 1],
+            header[start + 2],
+            header[start + 3],
+        ])
+    };
+    let (file_width, file_height) = (dimension(0), dimension(4));
+    if (file_width, file_height) != (width, height) {
+        bail!(
+            "Tree is for a {}x{} image, but the image is {}x{}; pass `-W {} -H {}` to use it",
+            file_width,
+            file_height,
+            width,
+            height,
+            file_width,
+            file_height
+        );
+    }
+    let (width, height) = (
+        usize::try_from(width).context("Failed to convert width u32 to usize")?,
+        usize::try_from(height).context("Failed to convert height u32 to usize")?,
+    );
+    ensure!(
+        points.len() == width * height,
+        "Tree file has {} pixels, but its header says {}",
+        points.len(),
+        width * height
+    );
+    let tree = points
+        .iter()
+        .map(|&bits| Neighbours::from_bits(bits).context("Tree file has an invalid pixel"))
+        .collect::<Result<Vec<_>>>()?;
+    let mut components = UnionFind::new(tree.len());
+    for (index, &point) in tree.iter().enumerate() {
+        for direction in point {
+            let (row, col) =
+                direction.step_wrapping_usize((index / width, index % width), (height, width));
+            let endpoint = row * width + col;
+            ensure!(
+                endpoint != index,
+                "Tree file has an edge joining a pixel to itself"
+            );
+            let backwards = direction
+                .reverse()
+                .context("Couldn't calculate reverse of direction to a point")?;
+            ensure!(
+                tree[endpoint].contains(backwards),
+                "Tree file has an edge that's only recorded at one end"
+            );
+            // every edge is seen from both ends, so only join it from one
+            if index < endpoint && !components.union(index, endpoint) {
+                bail!("Tree file has a cycle");
+            }
+        }
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::{trees::SpiralTree, GenTree, Progress};
+    use ::std::env;
+
+    const SIZE: (u32, u32) = (5, 4);
+
+    /// A saved spiral tree, along with the bytes of its file
+    fn saved(name: &str) -> (Vec<Neighbours>, Vec<u8>) {
+        let tree = SpiralTree.tree(5, 4, Progress::new(false)).unwrap();
+        let path = env::temp_dir().join(format!("lapidary-{}-{}", ::std::process::id(), name));
+        save(&path, SIZE, &tree).unwrap();
+        let bytes = fs::read(&path);
+        fs::remove_file(&path).ok();
+        (tree, bytes.unwrap())
+    }
+
+    #[test]
+    fn saved_trees_load_unchanged() {
+        let (tree, bytes) = saved("round-trip.tree");
+        assert_eq!(bytes.len(), HEADER_LEN + tree.len());
+        assert_eq!(parse(&bytes, SIZE).unwrap(), tree);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let (_, bytes) = saved("truncated.tree");
+        for len in [0, 4, HEADER_LEN - 1, HEADER_LEN, bytes.len() - 1] {
+            assert!(parse(&bytes[..len], SIZE).is_err(), "{} bytes", len);
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(parse(&longer, SIZE).is_err());
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let (tree, bytes) = saved("corrupt.tree");
+        let corrupt = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            change(&mut bytes);
+            parse(&bytes, SIZE)
+        };
+        // wrong magic bytes and version
+        assert!(corrupt(&|bytes| bytes[0] = b'X').is_err());
+        assert!(corrupt(&|bytes| bytes[MAGIC.len()] = VERSION + 1).is_err());
+        // an edge recorded at only one end
+        let leaf = HEADER_LEN
+            + tree
+                .iter()
+                .position(|point| point.count_set() == 1)
+                .unwrap();
+        assert!(corrupt(&|bytes| bytes[leaf] = 0).is_err());
+        // an extra edge, recorded at both ends, closing a cycle
+        let missing = (0..tree.len() - 5)
+            .find(|&index| !tree[index].contains(Neighbours::SOUTH))
+            .unwrap();
+        assert!(corrupt(&|bytes| {
+            bytes[HEADER_LEN + missing] |= Neighbours::SOUTH.bits();
+            bytes[HEADER_LEN + missing + 5] |= Neighbours::NORTH.bits();
+        })
+        .is_err());
+        // the wrong dimensions
+        assert!(parse(&bytes, (4, 5)).is_err());
+    }
+}
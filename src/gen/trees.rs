@@ -1,14 +1,13 @@
-use super::{colour::TestGen, prune_edges, GenTree};
+use super::{colour::TestGen, GenTree, Progress, Topology, WeightMode};
 use ::anyhow::{bail, Context, Result};
 use ::bitflags::bitflags;
-use ::indicatif::{ProgressBar, ProgressStyle};
 use ::rand::prelude::{Rng, SliceRandom};
 use ::rand_xoshiro::Xoshiro128PlusPlus;
-use ::std::mem::replace;
+use ::std::{collections::VecDeque, mem::replace};
 
 bitflags! {
   /// Bit flags for which neighbours of a pixel including diagonals are connected
-  pub(crate) struct Neighbours: u8 {
+  pub struct Neighbours: u8 {
     /// Represents the pixel above, in the negative y direction.
     const NORTH = 1 << 0;
     /// Represents the pixel above and to the right, in the positive x, negative y direction.
@@ -34,11 +33,16 @@ bitflags! {
     const EASTWARD = Self::EAST.bits | Self::SOUTHEAST.bits | Self::NORTHEAST.bits;
     /// West or northwest or southwest.
     const WESTWARD = Self::WEST.bits | Self::SOUTHWEST.bits | Self::NORTHWEST.bits;
+
+    /// North or east or south or west.
+    const ORTHOGONAL = Self::NORTH.bits | Self::EAST.bits | Self::SOUTH.bits | Self::WEST.bits;
+    /// Northeast or southeast or southwest or northwest.
+    const DIAGONAL = Self::NORTHEAST.bits | Self::SOUTHEAST.bits | Self::SOUTHWEST.bits | Self::NORTHWEST.bits;
   }
 }
 
 impl Neighbours {
-    pub(crate) const DIRECTIONS: [Neighbours; 8] = [
+    pub const DIRECTIONS: [Neighbours; 8] = [
         Neighbours::NORTH,
         Neighbours::NORTHEAST,
         Neighbours::EAST,
@@ -52,7 +56,7 @@ impl Neighbours {
     /// Return the backwards version of a direction
     ///
     /// Returns none if the Neighbours has more than one direction set
-    pub(crate) fn reverse(self: Self) -> Option<Neighbours> {
+    pub fn reverse(self) -> Option<Neighbours> {
         match self {
             Neighbours::NORTH => Some(Neighbours::SOUTH),
             Neighbours::NORTHEAST => Some(Neighbours::SOUTHWEST),
@@ -66,8 +70,60 @@ impl Neighbours {
         }
     }
 
-    /// Move a point in a direction
-    pub(crate) fn step(self: Self, (mut row, mut col): (u32, u32)) -> (u32, u32) {
+    /// Move a point in a direction, or return `None` if that would leave a grid
+    /// of the given size
+    pub fn step_checked(
+        self,
+        (row, col): (u32, u32),
+        (height, width): (u32, u32),
+    ) -> Option<(u32, u32)> {
+        let row = if Neighbours::NORTHWARD.contains(self) {
+            row.checked_sub(1)?
+        } else if Neighbours::SOUTHWARD.contains(self) {
+            row.checked_add(1).filter(|&row| row < height)?
+        } else {
+            row
+        };
+        let col = if Neighbours::WESTWARD.contains(self) {
+            col.checked_sub(1)?
+        } else if Neighbours::EASTWARD.contains(self) {
+            col.checked_add(1).filter(|&col| col < width)?
+        } else {
+            col
+        };
+        Some((row, col))
+    }
+
+    /// Move a point in a direction, or return `None` if that would leave a grid
+    /// of the given size, with the point represented by usize coordinates
+    pub fn step_checked_usize(
+        self,
+        (row, col): (usize, usize),
+        (height, width): (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let row = if Neighbours::NORTHWARD.contains(self) {
+            row.checked_sub(1)?
+        } else if Neighbours::SOUTHWARD.contains(self) {
+            row.checked_add(1).filter(|&row| row < height)?
+        } else {
+            row
+        };
+        let col = if Neighbours::WESTWARD.contains(self) {
+            col.checked_sub(1)?
+        } else if Neighbours::EASTWARD.contains(self) {
+            col.checked_add(1).filter(|&col| col < width)?
+        } else {
+            col
+        };
+        Some((row, col))
+    }
+
+    /// Move a point in a direction, with the point represented by usize coordinates
+    ///
+    /// The caller must make sure the step stays on the grid: stepping off the
+    /// top or left edge underflows. Use [`Neighbours::step_checked_usize`]
+    /// where that isn't already known.
+    fn step_usize(self, (mut row, mut col): (usize, usize)) -> (usize, usize) {
         if Neighbours::NORTHWARD.contains(self) {
             row -= 1
         } else if Neighbours::SOUTHWARD.contains(self) {
@@ -81,32 +137,66 @@ impl Neighbours {
         (row, col)
     }
 
-    /// Move a point in a direction, with the point represented by usize coordinates
-    fn step_usize(self: Self, (mut row, mut col): (usize, usize)) -> (usize, usize) {
+    /// Move a point in a direction on a grid whose opposite edges are joined
+    pub(crate) fn step_wrapping(
+        self,
+        (mut row, mut col): (u32, u32),
+        (height, width): (u32, u32),
+    ) -> (u32, u32) {
         if Neighbours::NORTHWARD.contains(self) {
-            row -= 1
+            row = row.checked_sub(1).unwrap_or(height - 1)
         } else if Neighbours::SOUTHWARD.contains(self) {
-            row += 1
+            row = (row + 1) % height
         }
         if Neighbours::WESTWARD.contains(self) {
-            col -= 1
+            col = col.checked_sub(1).unwrap_or(width - 1)
         } else if Neighbours::EASTWARD.contains(self) {
-            col += 1
+            col = (col + 1) % width
+        }
+        (row, col)
+    }
+
+    /// Move a point in a direction on a grid whose opposite edges are joined,
+    /// with the point represented by usize coordinates
+    pub(crate) fn step_wrapping_usize(
+        self,
+        (mut row, mut col): (usize, usize),
+        (height, width): (usize, usize),
+    ) -> (usize, usize) {
+        if Neighbours::NORTHWARD.contains(self) {
+            row = row.checked_sub(1).unwrap_or(height - 1)
+        } else if Neighbours::SOUTHWARD.contains(self) {
+            row = (row + 1) % height
+        }
+        if Neighbours::WESTWARD.contains(self) {
+            col = col.checked_sub(1).unwrap_or(width - 1)
+        } else if Neighbours::EASTWARD.contains(self) {
+            col = (col + 1) % width
         }
         (row, col)
     }
 
+    /// Number of directions set
+    pub fn count_set(self) -> u32 {
+        self.bits().count_ones()
+    }
+
+    /// Number of edges out of a pixel, the same as [`Neighbours::count_set`]
+    pub fn to_degree(self) -> usize {
+        self.count_set() as usize
+    }
+
     /// Turn a direction anticlockwise
     fn rotate_left(self, places: u32) -> Option<Self> {
         Self::from_bits(self.bits().rotate_right(places))
     }
 
-    /// Turn a direction clockwise
-    fn rotate_right(self, places: u32) -> Option<Self> {
-        Self::from_bits(self.bits().rotate_left(places))
-    }
-
-    fn random_direction<R: Rng, F: Fn(&Neighbours) -> u64>(
+    /// Pick one of the set directions with probability proportional to its
+    /// weight
+    ///
+    /// Weights are summed over the set bits directly, so this doesn't allocate
+    /// either.
+    fn random_direction<R: Rng, F: Fn(Neighbours) -> u64>(
         self,
         rng: &mut R,
         weight: F,
@@ -114,12 +204,36 @@ impl Neighbours {
         if self.is_empty() {
             bail!("No directions to choose randomly from")
         }
-        let directions = self.collect::<Vec<_>>();
-        let &ans = directions
-            .as_slice()
-            .choose_weighted(rng, weight)
-            .context("Weights assigned inadequately")?;
-        Ok(ans)
+        let mut weights = [0; 8];
+        let mut total = 0u64;
+        for (slot, direction) in weights.iter_mut().zip(self) {
+            *slot = weight(direction);
+            total = total
+                .checked_add(*slot)
+                .context("Weights assigned inadequately")?;
+        }
+        if total == 0 {
+            bail!("Weights assigned inadequately");
+        }
+        let mut target = rng.gen_range(0..total);
+        for (&slot, direction) in weights.iter().zip(self) {
+            match target.checked_sub(slot) {
+                Some(rest) => target = rest,
+                None => return Ok(direction),
+            }
+        }
+        bail!("Weighted choice overran the directions")
+    }
+
+    /// Pick one of the set directions with equal probability
+    ///
+    /// Needs no weights, so it's cheaper than `random_direction` in hot loops
+    /// such as random walks.
+    fn random_uniform_direction<R: Rng>(mut self, rng: &mut R) -> Option<Neighbours> {
+        match self.count_set() {
+            0 => None,
+            count => self.nth(rng.gen_range(0..count) as usize),
+        }
     }
 }
 
@@ -141,27 +255,217 @@ impl Iterator for Neighbours {
     }
 }
 
+/// How connected the pixels of a tree are
+///
+/// A spanning tree of `N` pixels has `N - 1` edges, or `N - R` for a forest
+/// grown from `R` start points.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TreeStats {
+    /// Fewest edges out of any pixel
+    pub min_degree: u32,
+    /// Most edges out of any pixel
+    pub max_degree: u32,
+    /// Average number of edges out of a pixel
+    pub mean_degree: f64,
+    /// Number of pixels with exactly one edge
+    pub leaf_count: usize,
+    /// Number of edges, each counted once rather than from both ends
+    pub total_edges: usize,
+}
+
+impl TreeStats {
+    /// Serialize as a single-line JSON object
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"min_degree": {}, "max_degree": {}, "mean_degree": {:.4}, "leaf_count": {}, "total_edges": {}}}"#,
+            self.min_degree, self.max_degree, self.mean_degree, self.leaf_count, self.total_edges
+        )
+    }
+}
+
+/// Degree statistics of a tree, given the edges out of each pixel
+pub fn tree_statistics(tree: &[Neighbours]) -> TreeStats {
+    let degrees = tree.iter().map(|point| point.count_set());
+    let ends = degrees.clone().map(|degree| degree as usize).sum::<usize>();
+    TreeStats {
+        min_degree: degrees.clone().min().unwrap_or(0),
+        max_degree: degrees.clone().max().unwrap_or(0),
+        mean_degree: if tree.is_empty() {
+            0.
+        } else {
+            ends as f64 / tree.len() as f64
+        },
+        leaf_count: degrees.filter(|&degree| degree == 1).count(),
+        total_edges: ends / 2,
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub(crate) struct SpiralTree;
 
+/// Relative likelihood of Prim's Algorithm following an edge out of a point
+///
+/// Only compared between the edges out of the same point, so weights that
+/// don't depend on the direction are the same as uniform ones.
+fn edge_weight(
+    weights: WeightMode,
+    (row, col): (usize, usize),
+    (centre_row, centre_col): (usize, usize),
+    direction: Neighbours,
+) -> u64 {
+    // usize always fits in u64 on supported platforms
+    let (row, col) = (row as u64, col as u64);
+    match weights {
+        WeightMode::DiagonalDrift => {
+            1 + if (Neighbours::NORTH | Neighbours::SOUTH).contains(direction) {
+                col * 2
+            } else if (Neighbours::EAST | Neighbours::WEST).contains(direction) {
+                row * 2
+            } else {
+                col + row
+            }
+        }
+        WeightMode::Uniform => 1,
+        WeightMode::Radial => {
+            let unit = |backward: Neighbours, forward: Neighbours| {
+                i64::from(forward.contains(direction)) - i64::from(backward.contains(direction))
+            };
+            // how far the edge leads away from the centre
+            let outward = (row as i64 - centre_row as i64)
+                * unit(Neighbours::NORTHWARD, Neighbours::SOUTHWARD)
+                + (col as i64 - centre_col as i64)
+                    * unit(Neighbours::WESTWARD, Neighbours::EASTWARD);
+            1 + 2 * outward.max(0) as u64
+        }
+        WeightMode::Horizontal => {
+            if (Neighbours::EAST | Neighbours::WEST).contains(direction) {
+                8
+            } else {
+                1
+            }
+        }
+        WeightMode::Vertical => {
+            if (Neighbours::NORTH | Neighbours::SOUTH).contains(direction) {
+                8
+            } else {
+                1
+            }
+        }
+        WeightMode::Diagonal => {
+            if Neighbours::DIAGONAL.contains(direction) {
+                8
+            } else {
+                1
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PrimTree {
+    pub(crate) rng: Xoshiro128PlusPlus,
+    pub(crate) initial_points: Vec<usize>,
+    pub(crate) topology: Topology,
+    pub(crate) weights: WeightMode,
+}
+
+/// Uniform spanning tree built from loop-erased random walks
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct WilsonTree {
+    pub(crate) rng: Xoshiro128PlusPlus,
+    pub(crate) initial_points: Vec<usize>,
+    pub(crate) topology: Topology,
+}
+
+/// Spanning tree built by a randomised depth-first search, which backtracks
+/// only when it gets stuck
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct DfsTree {
+    pub(crate) rng: Xoshiro128PlusPlus,
+    pub(crate) initial_point: usize,
+    pub(crate) topology: Topology,
+}
+
+/// Spanning forest built by a breadth-first search from every initial point at
+/// once, so each pixel is as few steps as possible from its root
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub(crate) struct PrimTree<F, G>
-where
-    F: Fn(&Neighbours) -> u64,
-    G: Fn((usize, usize)) -> F,
-{
+pub(crate) struct BfsTree {
     pub(crate) rng: Xoshiro128PlusPlus,
     pub(crate) initial_points: Vec<usize>,
-    pub(crate) weights: G,
+    pub(crate) topology: Topology,
+}
+
+/// Spanning tree built by Kruskal's Algorithm with uniformly random edge
+/// weights
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct KruskalTree {
+    pub(crate) rng: Xoshiro128PlusPlus,
+    pub(crate) topology: Topology,
+}
+
+/// Spanning tree built by recursively splitting the image into quadrants, which
+/// are joined through one random edge across each dividing line
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct QuadTree {
+    pub(crate) rng: Xoshiro128PlusPlus,
+    /// Smallest size of a quadrant along either axis, below which it's filled
+    /// with a linear scan rather than split further
+    pub(crate) min_size: usize,
+}
+
+/// Every pixel connected to the next along a generalised Hilbert curve, which
+/// fills rectangles of any size while keeping nearby pixels nearby in fill
+/// order
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct HilbertTree;
+
+/// Disjoint-set forest over pixel indices
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    /// Create a forest where every element is in its own set
+    pub(crate) fn new(len: usize) -> Self {
+        UnionFind {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    /// Find the representative of an element's set, compressing the path to it
+    fn find(&mut self, element: usize) -> usize {
+        let mut root = element;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut current = element;
+        while current != root {
+            current = replace(&mut self.parents[current], root);
+        }
+        root
+    }
+
+    /// Merge the sets of two elements, returning false if they were already
+    /// the same set
+    pub(crate) fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            ::std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+        true
+    }
 }
 
 impl GenTree for TestGen {
-    fn tree(
-        &mut self,
-        width: usize,
-        height: usize,
-        style: ProgressStyle,
-    ) -> Result<Vec<Neighbours>> {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
         let num_pixels = width * height;
         let u64_num_pixels = num_pixels
             .try_into()
@@ -169,21 +473,17 @@ impl GenTree for TestGen {
         let u64_width = width
             .try_into()
             .context("Failed to convert width usize to u64")?;
-        let pixels_bar = ProgressBar::new(u64_num_pixels)
-            .with_style(style.clone())
-            .with_prefix("All pixels");
+        let pixels_bar = progress.bar(u64_num_pixels, "All pixels");
         pixels_bar.tick();
         let mut points = vec![Neighbours::empty(); num_pixels];
         for val in pixels_bar.wrap_iter(points.iter_mut()) {
             *val = Neighbours::NORTH | Neighbours::SOUTH;
         }
-        let cols_bar = ProgressBar::new(u64_width)
-            .with_style(style)
-            .with_prefix("Bottom row");
+        let cols_bar = progress.bar(u64_width, "Bottom row");
         cols_bar.tick();
         pixels_bar.finish_with_message("Done!");
         let col = height / 2;
-        for i in cols_bar.wrap_iter(0..width as usize) {
+        for i in cols_bar.wrap_iter(0..width) {
             points[col * width + i] |= Neighbours::EAST | Neighbours::WEST;
         }
         cols_bar.finish_with_message("Done!");
@@ -192,36 +492,35 @@ impl GenTree for TestGen {
 }
 
 impl GenTree for SpiralTree {
-    fn tree(
-        &mut self,
-        width: usize,
-        height: usize,
-        style: ProgressStyle,
-    ) -> Result<Vec<Neighbours>> {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
         let num_pixels = width * height;
         let u64_num_pixels = num_pixels
             .try_into()
             .context("Failed to convert number of pixels usize to u64")?;
-        let bar = ProgressBar::new(u64_num_pixels)
-            .with_style(style.clone())
-            .with_prefix("Tree connections");
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
         bar.tick();
         let mut points = vec![Neighbours::empty(); num_pixels];
         let index = |row, col| row * width + col;
         let (mut row, mut col) = (0, 0);
         let mut direction = Neighbours::SOUTH;
+        // legs run down the first column, then alternate across and back up
+        // or down, each pair of legs one pixel shorter than the last
         let distance = |turns: isize| {
-            (if turns % 2 == 0 { width } else { height }) - (turns / 2).unsigned_abs() - 1
+            (if turns % 2 == 0 { width } else { height })
+                .saturating_sub((turns / 2).unsigned_abs() + 1)
         };
         let mut turns = -1;
-        while distance(turns) > 0 || distance(turns + 1) > 0 {
+        // the first leg can be empty on a one-pixel tall image, so stop once
+        // every pixel is reached rather than at the first empty leg
+        let mut reached = 1;
+        while reached < num_pixels {
             for _ in 0..distance(turns) {
                 let (prev_row, prev_col) = (row, col);
                 let reverse = direction
                     .reverse()
                     .context("Failed to reverse invalid direction")?;
                 {
-                    // move forward
+                    // move forward, which leg lengths keep on the image
                     let new_pos = direction.step_usize((row, col));
                     row = new_pos.0;
                     col = new_pos.1;
@@ -234,6 +533,10 @@ impl GenTree for SpiralTree {
                     .get_mut(index(prev_row, prev_col))
                     .context("Couldn't access previous position")? |= direction;
             }
+            reached += distance(turns);
+            if distance(turns) == 0 && turns >= 0 {
+                bail!("Spiral ran out of room before reaching every pixel");
+            }
             // turn left
             direction = direction
                 .rotate_left(2)
@@ -245,118 +548,104 @@ impl GenTree for SpiralTree {
     }
 }
 
-impl<F, G> GenTree for PrimTree<F, G>
-where
-    F: Fn(&Neighbours) -> u64,
-    G: Fn((usize, usize)) -> F + Sync + Send,
-{
-    fn tree(
-        &mut self,
-        width: usize,
-        height: usize,
-        style: ProgressStyle,
-    ) -> Result<Vec<Neighbours>> {
+impl GenTree for PrimTree {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
         let num_pixels = width * height;
         // initialise output to have no connections
         let mut output_points = vec![Neighbours::empty(); num_pixels];
         // initialise a vec with connections to every neighbour
-        let mut possible_edges = vec![Neighbours::all(); num_pixels];
-        prune_edges(width, height, style.clone(), &mut possible_edges)
-            .context("Failed to prune initial complete tree when generating spanning tree")?;
+        let mut possible_edges = self
+            .topology
+            .possible_edges(width, height, progress.clone())
+            .context("Failed to find possible edges when generating spanning tree")?;
         let u64_num_pixels = num_pixels
             .try_into()
             .context("Failed to convert number of pixels usize to u64")?;
         // create progress bar
-        let bar = ProgressBar::new(u64_num_pixels)
-            .with_style(style)
-            .with_prefix("Tree connections");
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
         // display progress bar
         bar.tick();
-        // store whether node has been added to the queue before as a neighbour of a
-        // processed node
-        let mut seen = vec![false; num_pixels];
         // store whether a node has been joined to another node as part of the tree
         let mut processed = vec![false; num_pixels];
-        // queue can only contain each point once
+        // points in the tree which still have edges out of it, each only once
         let mut point_queue = Vec::with_capacity(num_pixels);
+        // radial weights point away from the first initial point
+        let weights = self.weights;
+        let centre = self
+            .initial_points
+            .first()
+            .map_or((0, 0), |&index| (index / width, index % width));
         // start with configured initial points
         for &index in &self.initial_points {
-            point_queue.push(index);
-            *processed
-                .get_mut(index)
-                .context("Initial point out of range to set processed status")? = true;
+            if !replace(
+                processed
+                    .get_mut(index)
+                    .context("Initial point out of range to set processed status")?,
+                true,
+            ) {
+                point_queue.push(index);
+            }
+        }
+        for &index in &point_queue {
+            close_edges(
+                index,
+                &mut possible_edges,
+                &processed,
+                (width, height),
+                self.topology,
+            )?;
         }
-        // run through queue
+        // every edge left in possible_edges leads out of the tree, so the
+        // first edge drawn from a point is always taken
         while !point_queue.is_empty() {
-            // this should be processed
-            let from_index = self.rng.gen_range(0..point_queue.len());
-            let last_index = point_queue.len() - 1;
-            point_queue.swap(last_index, from_index);
             // randomly select point
-            let point_index = point_queue
-                .pop()
-                .context("Point vanished after moving it to the back of vector")?;
-            // get random edges until there are none left
-            // or break out of loop when an edge leads to a point that can be processed
-            while let (Ok(edge), point) = {
-                // access which edges are possible from this point
-                let point = possible_edges
-                    .get_mut(point_index)
-                    .context("Failed to access point ")?;
-                (
-                    point.random_direction(
-                        &mut self.rng,
-                        (self.weights)((point_index / width, point_index % width)),
-                    ),
-                    point,
-                )
-            } {
-                // this edge is no longer available
-                *point -= edge;
-                // follow edge
-                let (end_row, end_col) =
-                    edge.step_usize((point_index / width, point_index % width));
-                let endpoint = end_row * width + end_col;
-                // direction back to the randomly chosen point
-                let backwards = edge
-                    .reverse()
-                    .context("Couldn't calculate reverse of direction to a point")?;
-                // remove this edge from available ones
-                let endpoint_pointer = possible_edges
+            let from_index = self.rng.gen_range(0..point_queue.len());
+            let point_index = point_queue.swap_remove(from_index);
+            let point = *possible_edges
+                .get(point_index)
+                .context("Failed to access point ")?;
+            // the point's last edges were closed by its neighbours joining
+            if point.is_empty() {
+                bar.inc(1);
+                continue;
+            }
+            let position = (point_index / width, point_index % width);
+            let edge = point.random_direction(&mut self.rng, |direction| {
+                edge_weight(weights, position, centre, direction)
+            })?;
+            // follow edge
+            let (end_row, end_col) = self.topology.step_usize(edge, position, (height, width))?;
+            let endpoint = end_row * width + end_col;
+            if replace(
+                processed
                     .get_mut(endpoint)
-                    .context("Failed to remove neighbour point edge")?;
-                *endpoint_pointer -= backwards;
-                // if not already added to queue, add it to queue
-                if !replace(
-                    seen.get_mut(endpoint)
-                        .context("Couldn't read seen status of index")?,
-                    true,
-                ) {
-                    point_queue.push(endpoint)
-                }
-                // if not already added to tree, add it to tree, then break out of loop
-                if !replace(
-                    processed
-                        .get_mut(endpoint)
-                        .context("Couldn't read processed status of index")?,
-                    true,
-                ) {
-                    // add start of this edge to output
-                    *output_points
-                        .get_mut(point_index)
-                        .context("Failed to access point ")? |= edge;
-                    // add end of this edge to output
-                    *output_points
-                        .get_mut(endpoint)
-                        .context("Failed to add neighbour point edge")? |= backwards;
-                    break;
-                }
+                    .context("Couldn't read processed status of index")?,
+                true,
+            ) {
+                bail!("Open edge led back into the tree");
             }
-            if possible_edges
-                .get(point_index)
-                .context("Failed to access point to review possible edges")?
-                .is_empty()
-            {
+            // direction back to the randomly chosen point
+            let backwards = edge
+                .reverse()
+                .context("Couldn't calculate reverse of direction to a point")?;
+            // add start of this edge to output
+            *output_points
+                .get_mut(point_index)
+                .context("Failed to access point ")? |= edge;
+            // add end of this edge to output
+            *output_points
+                .get_mut(endpoint)
+                .context("Failed to add neighbour point edge")? |= backwards;
+            // closes this edge as well as any others into the tree
+            close_edges(
+                endpoint,
+                &mut possible_edges,
+                &processed,
+                (width, height),
+                self.topology,
+            )?;
+            point_queue.push(endpoint);
+            if possible_edges[point_index].is_empty() {
                 // point finished
                 bar.inc(1);
             } else {
@@ -369,3 +658,1000 @@ where
         Ok(output_points)
     }
 }
+
+/// Remove the edges between a point that just joined a tree and its
+/// neighbours already in the tree, from both ends
+fn close_edges(
+    index: usize,
+    possible_edges: &mut [Neighbours],
+    processed: &[bool],
+    (width, height): (usize, usize),
+    topology: Topology,
+) -> Result<()> {
+    let position = (index / width, index % width);
+    for direction in possible_edges[index] {
+        let (row, col) = topology.step_usize(direction, position, (height, width))?;
+        let neighbour = row * width + col;
+        if processed[neighbour] {
+            possible_edges[index] -= direction;
+            possible_edges[neighbour] -= direction
+                .reverse()
+                .context("Couldn't calculate reverse of direction to a point")?;
+        }
+    }
+    Ok(())
+}
+
+impl GenTree for WilsonTree {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
+        let num_pixels = width * height;
+        // initialise output to have no connections
+        let mut output_points = vec![Neighbours::empty(); num_pixels];
+        // every direction a walk could take from each point without leaving the grid
+        let possible_edges = self
+            .topology
+            .possible_edges(width, height, progress.clone())
+            .context("Failed to find possible edges when generating spanning tree")?;
+        let u64_num_pixels = num_pixels
+            .try_into()
+            .context("Failed to convert number of pixels usize to u64")?;
+        // progress is measured in points added to the tree, not steps walked
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
+        bar.tick();
+        // store whether a point has been joined to the tree
+        let mut visited = vec![false; num_pixels];
+        // start with configured initial points
+        for &index in &self.initial_points {
+            if !replace(
+                visited
+                    .get_mut(index)
+                    .context("Initial point out of range to set visited status")?,
+                true,
+            ) {
+                bar.inc(1);
+            }
+        }
+        // points on the current walk, and the direction taken out of each one
+        let mut path: Vec<usize> = Vec::new();
+        let mut path_directions: Vec<Neighbours> = Vec::new();
+        // position of each point within the current walk, if it is on it
+        let mut path_position = vec![None; num_pixels];
+        for start in 0..num_pixels {
+            if visited[start] {
+                continue;
+            }
+            path.push(start);
+            path_position[start] = Some(0);
+            let mut point = start;
+            // walk until the tree is hit
+            while !visited[point] {
+                let direction = possible_edges[point]
+                    .random_uniform_direction(&mut self.rng)
+                    .context("Point has no neighbours to walk to")?;
+                let (row, col) =
+                    direction.step_wrapping_usize((point / width, point % width), (height, width));
+                point = row * width + col;
+                match path_position[point] {
+                    // walked in a loop, so erase it back to the first visit
+                    Some(position) => {
+                        for &erased in &path[position + 1..] {
+                            path_position[erased] = None;
+                        }
+                        path.truncate(position + 1);
+                        path_directions.truncate(position);
+                    }
+                    None => {
+                        path_directions.push(direction);
+                        if !visited[point] {
+                            path_position[point] = Some(path.len());
+                            path.push(point);
+                        }
+                    }
+                }
+            }
+            // commit the loop-erased walk to the tree
+            for (&from, &direction) in path.iter().zip(&path_directions) {
+                let (row, col) =
+                    direction.step_wrapping_usize((from / width, from % width), (height, width));
+                let to = row * width + col;
+                output_points[from] |= direction;
+                output_points[to] |= direction
+                    .reverse()
+                    .context("Couldn't calculate reverse of direction to a point")?;
+                visited[from] = true;
+                path_position[from] = None;
+                bar.inc(1);
+            }
+            path.clear();
+            path_directions.clear();
+        }
+        bar.finish_with_message("Done");
+        Ok(output_points)
+    }
+}
+
+impl GenTree for DfsTree {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
+        let num_pixels = width * height;
+        // initialise output to have no connections
+        let mut output_points = vec![Neighbours::empty(); num_pixels];
+        // every direction the search could take from each point without leaving the grid
+        let possible_edges = self
+            .topology
+            .possible_edges(width, height, progress.clone())
+            .context("Failed to find possible edges when generating spanning tree")?;
+        let u64_num_pixels = num_pixels
+            .try_into()
+            .context("Failed to convert number of pixels usize to u64")?;
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
+        bar.tick();
+        // store whether a point has been joined to the tree
+        let mut visited = vec![false; num_pixels];
+        *visited
+            .get_mut(self.initial_point)
+            .context("Initial point out of range to set visited status")? = true;
+        bar.inc(1);
+        // explicit stack of the current path, since recursion would overflow
+        let mut stack = vec![self.initial_point];
+        // lowest pixel that might not be visited yet, for restarting the search
+        let mut next_unvisited = 0;
+        loop {
+            while let Some(&point) = stack.last() {
+                let position = (point / width, point % width);
+                let unvisited = possible_edges[point]
+                    .filter(|dir| {
+                        let (row, col) = dir.step_wrapping_usize(position, (height, width));
+                        !visited[row * width + col]
+                    })
+                    .collect::<Neighbours>();
+                match unvisited.random_uniform_direction(&mut self.rng) {
+                    Some(edge) => {
+                        let (row, col) = edge.step_wrapping_usize(position, (height, width));
+                        let endpoint = row * width + col;
+                        output_points[point] |= edge;
+                        output_points[endpoint] |= edge
+                            .reverse()
+                            .context("Couldn't calculate reverse of direction to a point")?;
+                        visited[endpoint] = true;
+                        bar.inc(1);
+                        stack.push(endpoint);
+                    }
+                    // dead end, so backtrack
+                    None => {
+                        stack.pop();
+                    }
+                }
+            }
+            // every point the search could reach is in the tree, so start
+            // again from the first one left over, if there is one
+            while visited.get(next_unvisited) == Some(&true) {
+                next_unvisited += 1;
+            }
+            if next_unvisited == num_pixels {
+                break;
+            }
+            visited[next_unvisited] = true;
+            bar.inc(1);
+            stack.push(next_unvisited);
+        }
+        bar.finish_with_message("Done");
+        Ok(output_points)
+    }
+}
+
+impl GenTree for BfsTree {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
+        let num_pixels = width * height;
+        // initialise output to have no connections
+        let mut output_points = vec![Neighbours::empty(); num_pixels];
+        let possible_edges = self
+            .topology
+            .possible_edges(width, height, progress.clone())
+            .context("Failed to find possible edges when generating spanning tree")?;
+        let u64_num_pixels = num_pixels
+            .try_into()
+            .context("Failed to convert number of pixels usize to u64")?;
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
+        bar.tick();
+        // store whether a point has been joined to the tree
+        let mut visited = vec![false; num_pixels];
+        let mut queue = VecDeque::with_capacity(num_pixels);
+        for &point in &self.initial_points {
+            *visited
+                .get_mut(point)
+                .context("Initial point out of range to set visited status")? = true;
+            bar.inc(1);
+            queue.push_back(point);
+        }
+        let mut directions = Vec::with_capacity(8);
+        while let Some(point) = queue.pop_front() {
+            let position = (point / width, point % width);
+            directions.clear();
+            directions.extend(possible_edges[point]);
+            directions.shuffle(&mut self.rng);
+            for &edge in &directions {
+                let (row, col) = edge.step_wrapping_usize(position, (height, width));
+                let endpoint = row * width + col;
+                if visited[endpoint] {
+                    continue;
+                }
+                output_points[point] |= edge;
+                output_points[endpoint] |= edge
+                    .reverse()
+                    .context("Couldn't calculate reverse of direction to a point")?;
+                visited[endpoint] = true;
+                bar.inc(1);
+                queue.push_back(endpoint);
+            }
+        }
+        bar.finish_with_message("Done");
+        Ok(output_points)
+    }
+}
+
+impl GenTree for KruskalTree {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
+        let num_pixels = width * height;
+        // initialise output to have no connections
+        let mut output_points = vec![Neighbours::empty(); num_pixels];
+        let possible_edges = self
+            .topology
+            .possible_edges(width, height, progress.clone())
+            .context("Failed to find possible edges when generating spanning tree")?;
+        // list each edge once, from the endpoint it leaves eastward or southward
+        let forwards =
+            Neighbours::EAST | Neighbours::SOUTHEAST | Neighbours::SOUTH | Neighbours::SOUTHWEST;
+        let mut edges = Vec::new();
+        for (index, &point) in possible_edges.iter().enumerate() {
+            let u32_index =
+                u32::try_from(index).context("Failed to convert pixel index usize to u32")?;
+            edges.extend((point & forwards).map(|edge| (u32_index, edge)));
+        }
+        edges.shuffle(&mut self.rng);
+        let u64_num_pixels = num_pixels
+            .try_into()
+            .context("Failed to convert number of pixels usize to u64")?;
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
+        bar.tick();
+        bar.inc(1);
+        let mut components = UnionFind::new(num_pixels);
+        let mut joined = 1;
+        for (u32_index, edge) in edges {
+            if joined == num_pixels {
+                break;
+            }
+            let point = u32_index as usize;
+            let (row, col) =
+                edge.step_wrapping_usize((point / width, point % width), (height, width));
+            let endpoint = row * width + col;
+            // only accept edges that join two separate components
+            if components.union(point, endpoint) {
+                output_points[point] |= edge;
+                output_points[endpoint] |= edge
+                    .reverse()
+                    .context("Couldn't calculate reverse of direction to a point")?;
+                joined += 1;
+                bar.inc(1);
+            }
+        }
+        bar.finish_with_message("Done");
+        Ok(output_points)
+    }
+}
+
+impl GenTree for HilbertTree {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
+        let num_pixels = width * height;
+        let u64_num_pixels = num_pixels
+            .try_into()
+            .context("Failed to convert number of pixels usize to u64")?;
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
+        bar.tick();
+        let mut output_points = vec![Neighbours::empty(); num_pixels];
+        let mut previous: Option<(usize, usize)> = None;
+        // whether the curve ever took a diagonal step, leaving a gap to fill
+        let mut gapped = false;
+        let mut visit = |row: usize, col: usize| {
+            bar.inc(1);
+            let Some((prev_row, prev_col)) = previous.replace((row, col)) else {
+                return;
+            };
+            let step = (
+                row as isize - prev_row as isize,
+                col as isize - prev_col as isize,
+            );
+            let (forward, backward) = match step {
+                (-1, 0) => (Neighbours::NORTH, Neighbours::SOUTH),
+                (0, 1) => (Neighbours::EAST, Neighbours::WEST),
+                (1, 0) => (Neighbours::SOUTH, Neighbours::NORTH),
+                (0, -1) => (Neighbours::WEST, Neighbours::EAST),
+                _ => {
+                    gapped = true;
+                    return;
+                }
+            };
+            output_points[prev_row * width + prev_col] |= forward;
+            output_points[row * width + col] |= backward;
+        };
+        if width >= height {
+            gilbert(
+                (0, 0),
+                (width as isize, 0),
+                (0, height as isize),
+                &mut visit,
+            );
+        } else {
+            gilbert(
+                (0, 0),
+                (0, height as isize),
+                (width as isize, 0),
+                &mut visit,
+            );
+        }
+        // odd by even rectangles can't be covered by a path of orthogonal
+        // steps, so the curve jumps diagonally once, which is bridged with
+        // any orthogonal edge between the two halves instead
+        if gapped {
+            let mut components = UnionFind::new(num_pixels);
+            for (index, &point) in output_points.iter().enumerate() {
+                for direction in point & (Neighbours::EAST | Neighbours::SOUTH) {
+                    let (row, col) = direction.step_usize((index / width, index % width));
+                    components.union(index, row * width + col);
+                }
+            }
+            for row in 0..height {
+                for col in 0..width {
+                    for direction in [Neighbours::EAST, Neighbours::SOUTH] {
+                        let Some((end_row, end_col)) =
+                            direction.step_checked_usize((row, col), (height, width))
+                        else {
+                            continue;
+                        };
+                        let (index, end) = (row * width + col, end_row * width + end_col);
+                        if components.union(index, end) {
+                            output_points[index] |= direction;
+                            output_points[end] |= direction
+                                .reverse()
+                                .context("Couldn't calculate reverse of direction to a point")?;
+                        }
+                    }
+                }
+            }
+        }
+        bar.finish_with_message("Curve done");
+        Ok(output_points)
+    }
+}
+
+/// Visit every pixel of a rectangle in generalised Hilbert curve order
+///
+/// The rectangle starts at `(x, y)` and spans the vectors `a`, along which
+/// the curve makes its way, and `b`, across it. Based on Jakub Červený's
+/// `gilbert2d`, which splits the rectangle into halves or thirds until it's a
+/// single row or column.
+fn gilbert(
+    (x, y): (isize, isize),
+    (ax, ay): (isize, isize),
+    (bx, by): (isize, isize),
+    visit: &mut impl FnMut(usize, usize),
+) {
+    let (w, h) = ((ax + ay).abs(), (bx + by).abs());
+    let (dax, day) = (ax.signum(), ay.signum());
+    let (dbx, dby) = (bx.signum(), by.signum());
+    if h == 1 || w == 1 {
+        let (steps, (dx, dy)) = if h == 1 {
+            (w, (dax, day))
+        } else {
+            (h, (dbx, dby))
+        };
+        for i in 0..steps {
+            visit((y + i * dy) as usize, (x + i * dx) as usize);
+        }
+        return;
+    }
+    let (mut ax2, mut ay2) = (ax.div_euclid(2), ay.div_euclid(2));
+    let (mut bx2, mut by2) = (bx.div_euclid(2), by.div_euclid(2));
+    let (w2, h2) = ((ax2 + ay2).abs(), (bx2 + by2).abs());
+    if 2 * w > 3 * h {
+        // long and thin, so split along the length only
+        if w2 % 2 == 1 && w > 2 {
+            (ax2, ay2) = (ax2 + dax, ay2 + day);
+        }
+        gilbert((x, y), (ax2, ay2), (bx, by), visit);
+        gilbert((x + ax2, y + ay2), (ax - ax2, ay - ay2), (bx, by), visit);
+    } else {
+        // up the near side, across the whole length, and back down
+        if h2 % 2 == 1 && h > 2 {
+            (bx2, by2) = (bx2 + dbx, by2 + dby);
+        }
+        gilbert((x, y), (bx2, by2), (ax2, ay2), visit);
+        gilbert((x + bx2, y + by2), (ax, ay), (bx - bx2, by - by2), visit);
+        gilbert(
+            (x + (ax - dax) + (bx2 - dbx), y + (ay - day) + (by2 - dby)),
+            (-bx2, -by2),
+            (-(ax - ax2), -(ay - ay2)),
+            visit,
+        );
+    }
+}
+
+impl GenTree for QuadTree {
+    fn tree(&mut self, width: usize, height: usize, progress: Progress) -> Result<Vec<Neighbours>> {
+        if self.min_size == 0 {
+            bail!("Quadrants must be allowed to be at least 1 pixel across");
+        }
+        let num_pixels = width * height;
+        let u64_num_pixels = num_pixels
+            .try_into()
+            .context("Failed to convert number of pixels usize to u64")?;
+        let bar = progress.bar(u64_num_pixels, "Tree connections");
+        bar.tick();
+        let mut output_points = vec![Neighbours::empty(); num_pixels];
+        let mut join = |(row, col): (usize, usize), direction: Neighbours| -> Result<()> {
+            let (end_row, end_col) = direction
+                .step_checked_usize((row, col), (height, width))
+                .context("Quadrant edge leads off the image")?;
+            *output_points
+                .get_mut(row * width + col)
+                .context("Couldn't access start of edge")? |= direction;
+            *output_points
+                .get_mut(end_row * width + end_col)
+                .context("Couldn't access end of edge")? |= direction
+                .reverse()
+                .context("Couldn't calculate reverse of direction to a point")?;
+            Ok(())
+        };
+        let min_size = self.min_size;
+        // split point that leaves at least the minimum size on both sides, if
+        // there is one
+        let split = |rng: &mut Xoshiro128PlusPlus, start: usize, end: usize| {
+            (end - start >= 2 * min_size).then(|| rng.gen_range(start + min_size..=end - min_size))
+        };
+        // rectangles waiting to be split, as (row_start, col_start, row_end,
+        // col_end) with exclusive ends
+        let mut rectangles = vec![(0, 0, height, width)];
+        while let Some((row_start, col_start, row_end, col_end)) = rectangles.pop() {
+            let row_split = split(&mut self.rng, row_start, row_end);
+            let col_split = split(&mut self.rng, col_start, col_end);
+            match (row_split, col_split) {
+                (None, None) => {
+                    // join every row into a line, and the rows together down
+                    // the first column
+                    for row in row_start..row_end {
+                        for col in col_start + 1..col_end {
+                            join((row, col - 1), Neighbours::EAST)?;
+                        }
+                        if row > row_start {
+                            join((row - 1, col_start), Neighbours::SOUTH)?;
+                        }
+                    }
+                    bar.inc(((row_end - row_start) * (col_end - col_start)) as u64);
+                }
+                (Some(row_split), None) => {
+                    let col = self.rng.gen_range(col_start..col_end);
+                    join((row_split - 1, col), Neighbours::SOUTH)?;
+                    rectangles.push((row_start, col_start, row_split, col_end));
+                    rectangles.push((row_split, col_start, row_end, col_end));
+                }
+                (None, Some(col_split)) => {
+                    let row = self.rng.gen_range(row_start..row_end);
+                    join((row, col_split - 1), Neighbours::EAST)?;
+                    rectangles.push((row_start, col_start, row_end, col_split));
+                    rectangles.push((row_start, col_split, row_end, col_end));
+                }
+                (Some(row_split), Some(col_split)) => {
+                    // join the quadrants either side of the vertical line,
+                    // then the top half to the bottom half
+                    let top_row = self.rng.gen_range(row_start..row_split);
+                    join((top_row, col_split - 1), Neighbours::EAST)?;
+                    let bottom_row = self.rng.gen_range(row_split..row_end);
+                    join((bottom_row, col_split - 1), Neighbours::EAST)?;
+                    let col = self.rng.gen_range(col_start..col_end);
+                    join((row_split - 1, col), Neighbours::SOUTH)?;
+                    rectangles.push((row_start, col_start, row_split, col_split));
+                    rectangles.push((row_start, col_split, row_split, col_end));
+                    rectangles.push((row_split, col_start, row_end, col_split));
+                    rectangles.push((row_split, col_split, row_end, col_end));
+                }
+            }
+        }
+        bar.finish_with_message("Done");
+        Ok(output_points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Connectivity, WeightMode};
+    use ::clap::ArgEnum;
+    use ::rand::SeedableRng;
+
+    fn prim_tree(
+        (width, height): (usize, usize),
+        connectivity: Connectivity,
+        tile: bool,
+        weights: WeightMode,
+        seed: u64,
+    ) -> Vec<Neighbours> {
+        PrimTree {
+            rng: Xoshiro128PlusPlus::seed_from_u64(seed),
+            initial_points: vec![(height / 2) * width + width / 2],
+            topology: Topology { connectivity, tile },
+            weights,
+        }
+        .tree(width, height, Progress::new(false))
+        .unwrap()
+    }
+
+    /// Check every edge has a matching reverse edge, and the edges reach every
+    /// pixel with no cycles
+    fn assert_spanning_tree(tree: &[Neighbours], (width, height): (usize, usize)) {
+        let endpoint = |index: usize, direction: Neighbours| {
+            let (row, col) =
+                direction.step_wrapping_usize((index / width, index % width), (height, width));
+            row * width + col
+        };
+        let mut edges = 0;
+        let mut components = UnionFind::new(tree.len());
+        for (index, &point) in tree.iter().enumerate() {
+            for direction in point {
+                let end = endpoint(index, direction);
+                assert!(
+                    tree[end].contains(direction.reverse().unwrap()),
+                    "edge {:?} out of {} has no reverse",
+                    direction,
+                    index
+                );
+                assert_ne!(
+                    index, end,
+                    "edge {:?} out of {} loops back",
+                    direction, index
+                );
+                // count each edge from its lower end
+                if index < end {
+                    edges += 1;
+                    assert!(components.union(index, end), "cycle through {}", index);
+                }
+            }
+        }
+        assert_eq!(edges, tree.len() - 1, "tree doesn't reach every pixel");
+    }
+
+    #[test]
+    fn prim_makes_spanning_trees() {
+        let sizes = [(1, 1), (1, 6), (6, 1), (2, 2), (3, 3), (7, 9), (16, 5)];
+        for &size in &sizes {
+            for connectivity in [Connectivity::Four, Connectivity::Eight] {
+                for tile in [false, true] {
+                    for weights in [WeightMode::Uniform, WeightMode::Radial] {
+                        for seed in 0..4 {
+                            let tree = prim_tree(size, connectivity, tile, weights, seed);
+                            assert_spanning_tree(&tree, size);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hilbert_makes_orthogonal_spanning_trees() {
+        let sizes = [
+            (1, 1),
+            (8, 8),
+            (13, 7),
+            (7, 13),
+            (5, 2),
+            (13, 6),
+            (6, 13),
+            (1, 9),
+            (192, 108),
+            (1920, 1080),
+        ];
+        for &(width, height) in &sizes {
+            let tree = HilbertTree
+                .tree(width, height, Progress::new(false))
+                .unwrap();
+            assert_spanning_tree(&tree, (width, height));
+            assert!(
+                tree.iter()
+                    .all(|point| !point.intersects(Neighbours::DIAGONAL)),
+                "diagonal edge in {}x{}",
+                width,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn dfs_makes_spanning_tree() {
+        let size = (50, 50);
+        for seed in 0..4 {
+            let tree = DfsTree {
+                rng: Xoshiro128PlusPlus::seed_from_u64(seed),
+                initial_point: 1275,
+                topology: Topology {
+                    connectivity: Connectivity::Eight,
+                    tile: false,
+                },
+            }
+            .tree(size.0, size.1, Progress::new(false))
+            .unwrap();
+            assert_spanning_tree(&tree, size);
+        }
+    }
+
+    #[test]
+    fn prim_makes_large_spanning_tree() {
+        let size = (1000, 1000);
+        let tree = prim_tree(
+            size,
+            Connectivity::Eight,
+            false,
+            WeightMode::DiagonalDrift,
+            0,
+        );
+        assert_spanning_tree(&tree, size);
+    }
+
+    /// Revisiting points and allocating for every random direction made this
+    /// take over half a second
+    #[test]
+    #[ignore = "timing is only meaningful with optimisations"]
+    fn prim_grows_a_million_pixel_tree_quickly() {
+        let start = ::std::time::Instant::now();
+        prim_tree(
+            (1000, 1000),
+            Connectivity::Four,
+            false,
+            WeightMode::DiagonalDrift,
+            0,
+        );
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed < ::std::time::Duration::from_millis(400),
+            "took {:?}",
+            elapsed
+        );
+    }
+
+    fn kruskal_tree(
+        (width, height): (usize, usize),
+        connectivity: Connectivity,
+        seed: u64,
+    ) -> Vec<Neighbours> {
+        KruskalTree {
+            rng: Xoshiro128PlusPlus::seed_from_u64(seed),
+            topology: Topology {
+                connectivity,
+                tile: false,
+            },
+        }
+        .tree(width, height, Progress::new(false))
+        .unwrap()
+    }
+
+    #[test]
+    fn kruskal_sets_two_bits_per_edge() {
+        let (width, height) = (10, 10);
+        let tree = kruskal_tree((width, height), Connectivity::Eight, 0);
+        let bits = tree.iter().map(|point| point.count_set()).sum::<u32>();
+        assert_eq!(bits as usize, 2 * (width * height - 1));
+    }
+
+    #[test]
+    fn four_connectivity_makes_orthogonal_spanning_trees() {
+        let topology = Topology {
+            connectivity: Connectivity::Four,
+            tile: false,
+        };
+        let sizes = [(1, 1), (1, 5), (5, 1), (2, 2), (4, 3), (7, 9)];
+        for &(width, height) in &sizes {
+            let centre = (height / 2) * width + width / 2;
+            for seed in 0..4 {
+                let rng = Xoshiro128PlusPlus::seed_from_u64(seed);
+                let tree_gens: [Box<dyn GenTree>; 5] = [
+                    Box::new(PrimTree {
+                        rng: rng.clone(),
+                        initial_points: vec![centre],
+                        topology,
+                        weights: WeightMode::Uniform,
+                    }),
+                    Box::new(WilsonTree {
+                        rng: rng.clone(),
+                        initial_points: vec![centre],
+                        topology,
+                    }),
+                    Box::new(DfsTree {
+                        rng: rng.clone(),
+                        initial_point: centre,
+                        topology,
+                    }),
+                    Box::new(BfsTree {
+                        rng: rng.clone(),
+                        initial_points: vec![centre],
+                        topology,
+                    }),
+                    Box::new(KruskalTree { rng, topology }),
+                ];
+                for mut tree_gen in tree_gens {
+                    let tree = tree_gen.tree(width, height, Progress::new(false)).unwrap();
+                    assert_spanning_tree(&tree, (width, height));
+                    assert!(
+                        tree.iter()
+                            .all(|point| !point.intersects(Neighbours::DIAGONAL)),
+                        "diagonal edge in {}x{}",
+                        width,
+                        height
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn step_checked_stays_on_the_grid() {
+        let (height, width) = (3, 4);
+        let edge_pixels = (0..height)
+            .flat_map(|row| (0..width).map(move |col| (row, col)))
+            .filter(|&(row, col)| row == 0 || col == 0 || row == height - 1 || col == width - 1);
+        for (row, col) in edge_pixels {
+            for direction in Neighbours::DIRECTIONS {
+                let (end_row, end_col) = {
+                    let (mut row, mut col) = (i64::from(row), i64::from(col));
+                    if Neighbours::NORTHWARD.contains(direction) {
+                        row -= 1;
+                    } else if Neighbours::SOUTHWARD.contains(direction) {
+                        row += 1;
+                    }
+                    if Neighbours::WESTWARD.contains(direction) {
+                        col -= 1;
+                    } else if Neighbours::EASTWARD.contains(direction) {
+                        col += 1;
+                    }
+                    (row, col)
+                };
+                let on_grid = (0..i64::from(height)).contains(&end_row)
+                    && (0..i64::from(width)).contains(&end_col);
+                let expected = on_grid.then_some((end_row as u32, end_col as u32));
+                assert_eq!(
+                    direction.step_checked((row, col), (height, width)),
+                    expected,
+                    "{:?} from {},{}",
+                    direction,
+                    col,
+                    row
+                );
+                assert_eq!(
+                    direction.step_checked_usize(
+                        (row as usize, col as usize),
+                        (height as usize, width as usize)
+                    ),
+                    expected.map(|(row, col)| (row as usize, col as usize)),
+                    "{:?} from {},{}",
+                    direction,
+                    col,
+                    row
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn kruskal_makes_spanning_trees() {
+        let sizes = [(1, 1), (1, 6), (6, 1), (2, 2), (3, 3), (7, 9), (16, 5)];
+        for &size in &sizes {
+            for connectivity in [Connectivity::Four, Connectivity::Eight] {
+                for seed in 0..4 {
+                    assert_spanning_tree(&kruskal_tree(size, connectivity, seed), size);
+                }
+            }
+        }
+    }
+
+    fn wilson_tree(
+        (width, height): (usize, usize),
+        connectivity: Connectivity,
+        tile: bool,
+        seed: u64,
+    ) -> Vec<Neighbours> {
+        WilsonTree {
+            rng: Xoshiro128PlusPlus::seed_from_u64(seed),
+            initial_points: vec![(height / 2) * width + width / 2],
+            topology: Topology { connectivity, tile },
+        }
+        .tree(width, height, Progress::new(false))
+        .unwrap()
+    }
+
+    #[test]
+    fn wilson_makes_spanning_trees() {
+        let sizes = [(1, 1), (1, 6), (6, 1), (2, 2), (3, 3), (7, 9), (16, 5)];
+        for &size in &sizes {
+            for connectivity in [Connectivity::Four, Connectivity::Eight] {
+                for tile in [false, true] {
+                    for seed in 0..4 {
+                        let tree = wilson_tree(size, connectivity, tile, seed);
+                        assert_spanning_tree(&tree, size);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wilson_repeats_with_the_same_seed() {
+        let size = (30, 20);
+        let tree = wilson_tree(size, Connectivity::Eight, false, 9);
+        assert_eq!(tree, wilson_tree(size, Connectivity::Eight, false, 9));
+        assert_ne!(tree, wilson_tree(size, Connectivity::Eight, false, 10));
+    }
+
+    #[test]
+    fn prim_with_four_connectivity_has_no_diagonal_edges() {
+        let size = (12, 9);
+        for &weights in WeightMode::value_variants() {
+            for tile in [false, true] {
+                let tree = prim_tree(size, Connectivity::Four, tile, weights, 3);
+                assert_spanning_tree(&tree, size);
+                assert!(
+                    tree.iter()
+                        .all(|point| !point.intersects(Neighbours::DIAGONAL)),
+                    "diagonal edge with {:?} weights",
+                    weights
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bfs_makes_shortest_path_spanning_trees() {
+        let (width, height) = (20, 20);
+        let (root_row, root_col) = (7, 12);
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            for seed in 0..4 {
+                let tree = BfsTree {
+                    rng: Xoshiro128PlusPlus::seed_from_u64(seed),
+                    initial_points: vec![root_row * width + root_col],
+                    topology: Topology {
+                        connectivity,
+                        tile: false,
+                    },
+                }
+                .tree(width, height, Progress::new(false))
+                .unwrap();
+                assert_spanning_tree(&tree, (width, height));
+                // every pixel is as few steps from the root as the grid allows
+                let mut depths = vec![None; tree.len()];
+                depths[root_row * width + root_col] = Some(0);
+                let mut queue = VecDeque::from([(root_row, root_col)]);
+                while let Some((row, col)) = queue.pop_front() {
+                    let depth = depths[row * width + col].unwrap();
+                    for direction in tree[row * width + col] {
+                        let (row, col) = direction.step_usize((row, col));
+                        if depths[row * width + col].is_none() {
+                            depths[row * width + col] = Some(depth + 1);
+                            queue.push_back((row, col));
+                        }
+                    }
+                }
+                for (index, depth) in depths.into_iter().enumerate() {
+                    let (rows, cols) = (
+                        (index / width).abs_diff(root_row),
+                        (index % width).abs_diff(root_col),
+                    );
+                    let distance = match connectivity {
+                        Connectivity::Four => rows + cols,
+                        Connectivity::Eight => rows.max(cols),
+                    };
+                    assert_eq!(depth, Some(distance), "pixel {}", index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quad_tree_makes_spanning_trees() {
+        let sizes = [(64, 64), (1, 1), (1, 9), (9, 1), (13, 7), (33, 20)];
+        for &(width, height) in &sizes {
+            for min_size in [1, 2, 4, 16] {
+                for seed in 0..4 {
+                    let tree = QuadTree {
+                        rng: Xoshiro128PlusPlus::seed_from_u64(seed),
+                        min_size,
+                    }
+                    .tree(width, height, Progress::new(false))
+                    .unwrap();
+                    assert_spanning_tree(&tree, (width, height));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_makes_spanning_trees() {
+        let sizes = [
+            (1, 1),
+            (1, 7),
+            (7, 1),
+            (5, 3),
+            (3, 5),
+            (4, 4),
+            (3, 8),
+            (8, 3),
+        ];
+        for &(width, height) in &sizes {
+            let tree = SpiralTree
+                .tree(width, height, Progress::new(false))
+                .unwrap();
+            assert_spanning_tree(&tree, (width, height));
+        }
+    }
+
+    #[test]
+    fn each_weight_mode_weights_edges_differently() {
+        let (height, width) = (5, 6);
+        let centre = (2, 3);
+        let weights_over_grid = |weights| {
+            (0..height)
+                .flat_map(|row| (0..width).map(move |col| (row, col)))
+                .flat_map(|point| {
+                    Neighbours::DIRECTIONS
+                        .into_iter()
+                        .map(move |direction| edge_weight(weights, point, centre, direction))
+                })
+                .collect::<Vec<_>>()
+        };
+        let modes = WeightMode::value_variants();
+        let grids = modes
+            .iter()
+            .map(|&mode| weights_over_grid(mode))
+            .collect::<Vec<_>>();
+        for (mode, grid) in modes.iter().zip(&grids) {
+            assert!(grid.iter().all(|&weight| weight > 0), "{:?}", mode);
+        }
+        for i in 0..modes.len() {
+            for j in i + 1..modes.len() {
+                assert_ne!(grids[i], grids[j], "{:?} and {:?}", modes[i], modes[j]);
+            }
+        }
+        let weight = |mode, point, direction| edge_weight(mode, point, centre, direction);
+        assert!(
+            weight(WeightMode::Horizontal, centre, Neighbours::EAST)
+                > weight(WeightMode::Horizontal, centre, Neighbours::NORTH)
+        );
+        assert!(
+            weight(WeightMode::Vertical, centre, Neighbours::SOUTH)
+                > weight(WeightMode::Vertical, centre, Neighbours::WEST)
+        );
+        assert!(
+            weight(WeightMode::Diagonal, centre, Neighbours::NORTHEAST)
+                > weight(WeightMode::Diagonal, centre, Neighbours::EAST)
+        );
+        assert!(
+            weight(WeightMode::Radial, (0, 0), Neighbours::NORTHWEST)
+                > weight(WeightMode::Radial, (0, 0), Neighbours::SOUTHEAST)
+        );
+        assert!(
+            weight(WeightMode::DiagonalDrift, (4, 0), Neighbours::EAST)
+                > weight(WeightMode::DiagonalDrift, (4, 0), Neighbours::NORTH)
+        );
+    }
+
+    #[test]
+    fn each_weight_mode_grows_a_different_tree() {
+        let size = (24, 18);
+        let modes = WeightMode::value_variants();
+        let trees = modes
+            .iter()
+            .map(|&mode| prim_tree(size, Connectivity::Eight, false, mode, 5))
+            .collect::<Vec<_>>();
+        for i in 0..modes.len() {
+            assert_spanning_tree(&trees[i], size);
+            for j in i + 1..modes.len() {
+                assert_ne!(trees[i], trees[j], "{:?} and {:?}", modes[i], modes[j]);
+            }
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! Generate pictures using random flood fill.
+//!
+//! An image is made by connecting its pixels into a random spanning tree, then
+//! walking the tree outward from a start pixel, picking each pixel's colour
+//! from its parent's. [`GeneratorConfig`] chooses between the built-in
+//! generators for both steps, and new ones can be plugged in by implementing
+//! [`GenTree`] and [`GenColour`] and calling [`generate_with`].
+
+mod animation;
+mod config;
+mod gen;
+
+pub use config::{
+    ColourGen, Connectivity, Depth, GeneratorConfig, Interrupt, ProgressOutput, StartColour,
+    Symmetry, TreeGen, Verbosity, WeightMode, WrapMode,
+};
+pub use gen::{
+    generate, generate_dynamic, generate_maze, generate_maze_timed, generate_timed, generate_with,
+    prune_edges, render_maze, tree_statistics, ColourChannel, ColourPixel, GenColour, GenTree,
+    Neighbours, Progress, Timings, TreeStats,
+};
@@ -1,30 +1,253 @@
-use ::anyhow::{Context, Result};
-use ::clap::{ArgEnum, Parser};
+use ::anyhow::{bail, Context, Result};
+use ::clap::{ArgEnum, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueSource};
+use ::image::{DynamicImage, Rgb};
+use ::indicatif::ProgressStyle;
+use ::std::{
+    ffi::{OsStr, OsString},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-mod gen;
+use ::lapidary::{
+    generate_maze_timed, generate_timed, ColourGen, Connectivity, Depth, GeneratorConfig,
+    Interrupt, Progress, ProgressOutput, StartColour, Symmetry, TreeGen, Verbosity, WeightMode,
+    WrapMode,
+};
 
-use self::gen::new_image;
+mod config_file;
+mod ctrl_c;
+mod output;
+mod palette;
+mod seed;
+mod sidecar;
 
 /// Generate pictures using random flood fill.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[clap(name = env!("CARGO_PKG_NAME"), version = env!("CARGO_PKG_VERSION"))]
 struct Cli {
-    /// Path to save output image to (supports .png and .jpg)
-    #[clap(name = "output-file", parse(from_os_str))]
-    out_path: std::path::PathBuf,
+    /// Path to save output image to, in a format picked by its extension
+    /// unless `--format` is given
+    ///
+    /// May be left out when writing the image to stdout with `--no-save`.
+    #[clap(
+        name = "output-file",
+        parse(from_os_str),
+        required_unless_present_any = &["no-save", "dump-config"]
+    )]
+    out_path: Option<std::path::PathBuf>,
 
     /// Image width in pixels
-    #[clap(short = 'W', long, default_value = "1000", help_heading = "DIMENSIONS")]
+    #[clap(
+        short = 'W',
+        long,
+        default_value = "1000",
+        validator = check_nonzero,
+        help_heading = "DIMENSIONS"
+    )]
     width: u32,
 
     /// Image height in pixels
-    #[clap(short = 'H', long, default_value = "1000", help_heading = "DIMENSIONS")]
+    #[clap(
+        short = 'H',
+        long,
+        default_value = "1000",
+        validator = check_nonzero,
+        help_heading = "DIMENSIONS"
+    )]
     height: u32,
 
-    /// Whether to skip writing output image to a file [unimplemented]
+    /// Generate the image this many times larger in each dimension, then scale
+    /// it down to the requested size, to smooth out jagged edges
+    ///
+    /// Memory use grows with the square of the factor.
+    #[clap(
+        long,
+        default_value = "1",
+        possible_values = ["1", "2", "4"],
+        help_heading = "DIMENSIONS"
+    )]
+    supersample: u32,
+
+    /// Mirror the left half into the right, the top half into the bottom, or
+    /// the top left quarter into the others
+    ///
+    /// Only the mirrored part is generated, so `-X` and `-Y` are fractions of
+    /// it rather than of the whole image, and masks and trees must match its
+    /// size.
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "none",
+        ignore_case = true,
+        help_heading = "DIMENSIONS"
+    )]
+    symmetry: Symmetry,
+
+    /// Take the width and height from an existing image file
+    ///
+    /// `-W` and `-H` still win if given.
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "IMAGE",
+        help_heading = "DIMENSIONS"
+    )]
+    match_dimensions: Option<std::path::PathBuf>,
+
+    /// Write the image to stdout as well as to any output file
     #[clap(short = 'N', long)]
     no_save: bool,
 
+    /// Don't draw progress bars, like `--progress-output none`
+    ///
+    /// Errors and warnings are still printed.
+    #[clap(short = 'q', long)]
+    quiet: bool,
+
+    /// Where to draw progress bars: `stderr`, `stdout`, `none`, or a file path
+    ///
+    /// Bars are only drawn on terminals. A file gets a plain line for each
+    /// stage that's moved on, once a second. Progress can't go to stdout when
+    /// the image or a batch's paths are written there.
+    #[clap(
+        long,
+        default_value = "stderr",
+        value_name = "OUTPUT",
+        parse(from_os_str = parse_progress_output)
+    )]
+    progress_output: ProgressOutput,
+
+    /// Log each stage of generation on stderr, timed from when the program
+    /// started
+    #[clap(short = 'v', long)]
+    verbose: bool,
+
+    /// Print how long each phase took as a JSON object on stderr once the image
+    /// is saved
+    ///
+    /// Times are in milliseconds, under the keys `tree_gen_ms`, `prune_ms`,
+    /// `colour_ms`, `save_ms` and `total_ms`. In maze mode, `colour_ms` is the
+    /// time spent drawing the maze.
+    #[clap(long)]
+    timing: bool,
+
+    /// Print statistics about the tree as a JSON object on stderr once it's
+    /// generated
+    ///
+    /// Counts edges out of each pixel under the keys `min_degree`,
+    /// `max_degree`, `mean_degree` and `leaf_count`, and edges in total under
+    /// `total_edges`. A tree on N pixels from one start point has N - 1 edges.
+    #[clap(long)]
+    print_stats: bool,
+
+    /// Format to encode the image in, whatever the output file's extension
+    ///
+    /// Without it, the output file's format is picked by its extension, and
+    /// images written to stdout are PNG. WebP isn't supported, since the image
+    /// library can only decode it.
+    #[clap(
+        long,
+        alias = "output-format",
+        alias = "fmt",
+        arg_enum,
+        ignore_case = true
+    )]
+    format: Option<OutputFormat>,
+
+    /// Quality of JPEG output, from 1 to 100
+    #[clap(long, default_value = "85", validator = check_jpeg_quality)]
+    jpeg_quality: u8,
+
+    /// Largest allowed size of the output file in bytes
+    ///
+    /// If the image is too big, stronger PNG compression, then palette
+    /// quantization, then JPEG with decreasing quality are tried in turn until
+    /// it fits. The output file extension is changed to match the format used.
+    #[clap(long)]
+    max_bytes: Option<usize>,
+
+    /// Whether to save the coloured image, the tree drawn as a maze, or both
+    ///
+    /// Mazes have a white cell for each pixel, with black walls between cells
+    /// that aren't joined, so they're twice the size plus one. They need
+    /// `--connectivity 4`, since walls can't show diagonal edges. With `both`,
+    /// the maze is saved beside the output file as `<stem>_maze.<ext>`.
+    #[clap(long, arg_enum, ignore_case = true, default_value = "colours")]
+    output_mode: OutputMode,
+
+    /// Invert the red, green and blue channels of the finished image
+    ///
+    /// Like `--brightness` and `--contrast`, this only changes the saved
+    /// image, not how its colours are generated. Inverting comes first, then
+    /// brightness, then contrast.
+    #[clap(long)]
+    invert: bool,
+
+    /// Amount to add to every colour channel of the finished image, from -255
+    /// to 255
+    #[clap(
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        validator = check_brightness
+    )]
+    brightness: i16,
+
+    /// Percentage to increase the contrast of the finished image by, or
+    /// decrease it by if negative, where -100 flattens it to grey
+    #[clap(
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        validator = check_contrast
+    )]
+    contrast: f32,
+
+    /// Write the generation parameters to `<output_stem>.lapidary.json` next to
+    /// the output file
+    ///
+    /// The seed is always recorded, even if it was picked at random.
+    #[clap(long, requires = "output-file")]
+    sidecar: bool,
+
+    /// Read generation parameters from a sidecar file written by `--sidecar`
+    ///
+    /// Parameters given on the command line override the sidecar, including
+    /// the seed: `--seed`, `--seed-phrase` or `--daily` win over the seed in
+    /// the sidecar.
+    #[clap(long, parse(from_os_str), value_name = "PATH")]
+    from_sidecar: Option<std::path::PathBuf>,
+
+    /// Read options from a TOML file of `key = value` lines, keyed by the
+    /// options' long names, like `step_size = 3` or `start = ["0.2,0.8"]`
+    ///
+    /// Options given on the command line override the file, and the file
+    /// overrides `--from-sidecar`.
+    #[clap(long, parse(from_os_str), value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+
+    /// Print the options given on the command line and by `--config` as TOML
+    /// that `--config` can read, then exit without generating an image
+    #[clap(long)]
+    dump_config: bool,
+
+    /// Generate this many images, saved as `<stem>_0001.<ext>` onwards
+    ///
+    /// If the output path contains `{}` or `{seed}`, the image's number or
+    /// seed is put there instead. Each image uses the seed plus its number
+    /// minus one, so the first image matches a single run with the same seed.
+    /// If no seed is given, a random one is picked and printed. Each path is
+    /// printed with its seed on stdout. Images that fail are reported and skipped.
+    #[clap(
+        short = 'n',
+        long,
+        requires = "output-file",
+        conflicts_with = "no-save",
+        validator = check_nonzero
+    )]
+    count: Option<u32>,
+
     /// Which generator to use for calculating pixel colours
     #[clap(
         short = 'C',
@@ -45,46 +268,566 @@ struct Cli {
     )]
     tree_gen: TreeGen,
 
+    /// Colour of the first pixel, as a hex code like `#ff8800`, or `random`
+    ///
+    /// A random start colour is picked using the seed, so it's reproducible.
+    /// The test colour generator's output is only defined when starting at
+    /// black; the other generators work from any start colour.
+    #[clap(
+        long,
+        default_value = "#000000",
+        parse(try_from_str = parse_start_colour),
+        help_heading = "COLOURS"
+    )]
+    start_colour: StartColour,
+
+    /// Start each tree with the colour of the same pixel in this image,
+    /// instead of `--start-colour`
+    ///
+    /// The image is scaled to the output's size if it doesn't match.
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "IMAGE",
+        conflicts_with = "start-colour",
+        help_heading = "COLOURS"
+    )]
+    start_image: Option<std::path::PathBuf>,
+
+    /// Whether pixels connect to all 8 neighbours or only the 4 orthogonal ones
+    #[clap(
+        short = 'K',
+        long,
+        arg_enum,
+        default_value = "8",
+        help_heading = "FILL ORDER"
+    )]
+    connectivity: Connectivity,
+
+    /// How the Prim tree generator weights the edges out of each pixel
+    #[clap(
+        long = "weights",
+        alias = "prim-weight",
+        arg_enum,
+        ignore_case = true,
+        default_value = "diagonal-drift",
+        help_heading = "FILL ORDER"
+    )]
+    weight_mode: WeightMode,
+
+    /// Smallest quadrant size along either axis for the quad tree generator,
+    /// below which quadrants are filled with a linear scan
+    #[clap(long, default_value = "4", validator = check_nonzero, help_heading = "FILL ORDER")]
+    quad_min_size: usize,
+
+    /// Join opposite edges of the image so that it tiles seamlessly
+    ///
+    /// Not supported by the test, spiral or quad tree generators.
+    #[clap(long, alias = "wrap", help_heading = "FILL ORDER")]
+    tile: bool,
+
+    /// Give the image an alpha channel, so it can be partly transparent
+    ///
+    /// The random colour generator perturbs alpha like any other channel; other
+    /// generators keep the start alpha. JPEG output needs `--background`.
+    #[clap(long, help_heading = "COLOURS")]
+    alpha: bool,
+
+    /// Alpha of the first pixel if the image has an alpha channel
+    #[clap(long, default_value = "255", help_heading = "COLOURS")]
+    start_alpha: u8,
+
+    /// Bits per colour channel
+    ///
+    /// 16-bit images can only be saved as PNG. Only the rand colour generator
+    /// makes use of the extra precision; the others work at 8 bits and scale
+    /// their colours up.
+    #[clap(long, arg_enum, default_value = "8", help_heading = "COLOURS")]
+    depth: Depth,
+
+    /// Save a single grey channel instead of red, green and blue
+    ///
+    /// Colours are converted to their luminance, so this is best paired with
+    /// `-C grey`.
+    #[clap(long, alias = "greyscale-output", help_heading = "COLOURS")]
+    grayscale_output: bool,
+
+    /// Colour to flatten transparent images onto when saving to formats without
+    /// alpha, as a hex code
+    #[clap(long, parse(try_from_str = parse_hex_colour), help_heading = "COLOURS")]
+    background: Option<Rgb<u8>>,
+
     /// Maximum displacement of a colour channel if using a random colour
     /// generator
+    ///
+    /// Measured in the channel's own range, so up to 255 at 8 bits per channel
+    /// or 65535 for the rand generator at 16 bits per channel.
     #[clap(short = 'D', default_value = "10", help_heading = "COLOURS")]
-    step_size: u8,
+    step_size: u16,
+
+    /// Maximum displacement of the red channel, overriding `-D`
+    #[clap(long, help_heading = "COLOURS")]
+    step_r: Option<u16>,
+
+    /// Maximum displacement of the green channel, overriding `-D`
+    #[clap(long, help_heading = "COLOURS")]
+    step_g: Option<u16>,
+
+    /// Maximum displacement of the blue channel, overriding `-D`
+    #[clap(long, help_heading = "COLOURS")]
+    step_b: Option<u16>,
+
+    /// What the rand colour generator does when a channel runs past 0 or its
+    /// maximum
+    ///
+    /// Saturating lets colours bunch up at black, white and the primaries in
+    /// large images; wrapping and mirroring keep them moving. Mirroring is an
+    /// unbiased random walk, reaching every value equally often in the long run.
+    #[clap(
+        long,
+        alias = "colour-wrap",
+        arg_enum,
+        ignore_case = true,
+        default_value = "mirror",
+        help_heading = "COLOURS"
+    )]
+    wrap_mode: WrapMode,
+
+    /// Maximum change in hue in degrees if using the HSV or hue colour generator
+    #[clap(long, default_value = "10.0", validator = check_non_negative, help_heading = "COLOURS")]
+    hue_step: f32,
+
+    /// Maximum change in saturation, out of 1, if using the HSV colour generator
+    #[clap(long, default_value = "0.05", validator = check_non_negative, help_heading = "COLOURS")]
+    sat_step: f32,
+
+    /// Maximum change in value, out of 1, if using the HSV colour generator
+    #[clap(long, default_value = "0.05", validator = check_non_negative, help_heading = "COLOURS")]
+    val_step: f32,
+
+    /// Colour at the root if using the gradient colour generator, as a hex code
+    ///
+    /// Replaces the start colour for the gradient generator.
+    #[clap(
+        long,
+        alias = "gradient-start",
+        default_value = "#000000",
+        parse(try_from_str = parse_hex_colour),
+        help_heading = "COLOURS"
+    )]
+    gradient_from: Rgb<u8>,
+
+    /// Colour the gradient colour generator fades to, as a hex code
+    #[clap(
+        long,
+        alias = "gradient-end",
+        default_value = "#ffffff",
+        parse(try_from_str = parse_hex_colour),
+        help_heading = "COLOURS"
+    )]
+    gradient_to: Rgb<u8>,
+
+    /// Number of pixels along the tree to fade over if using the gradient
+    /// colour generator, after which the colour stays the same
+    #[clap(long, default_value = "1000", validator = check_nonzero, help_heading = "COLOURS")]
+    gradient_length: u32,
+
+    /// Fade back towards the root colour after reaching the end of the
+    /// gradient, rather than staying at the end colour
+    #[clap(long, help_heading = "COLOURS")]
+    gradient_bounce: bool,
+
+    /// Lowest saturation, out of 1, if using the hue colour generator
+    #[clap(long, default_value = "0.6", validator = check_unit_interval, help_heading = "COLOURS")]
+    min_saturation: f32,
+
+    /// Highest saturation, out of 1, if using the hue colour generator
+    #[clap(long, default_value = "1.0", validator = check_unit_interval, help_heading = "COLOURS")]
+    max_saturation: f32,
+
+    /// Lowest value, out of 1, if using the hue colour generator
+    #[clap(long, default_value = "0.6", validator = check_unit_interval, help_heading = "COLOURS")]
+    min_value: f32,
+
+    /// Highest value, out of 1, if using the hue colour generator
+    #[clap(long, default_value = "1.0", validator = check_unit_interval, help_heading = "COLOURS")]
+    max_value: f32,
+
+    /// Saturation, out of 1, if using the direction-hue colour generator
+    #[clap(long, default_value = "0.8", validator = check_unit_interval, help_heading = "COLOURS")]
+    dir_saturation: f32,
+
+    /// Value, out of 1, if using the direction-hue colour generator
+    #[clap(long, default_value = "0.9", validator = check_unit_interval, help_heading = "COLOURS")]
+    dir_value: f32,
+
+    /// How closely hue follows direction if using the direction-hue colour
+    /// generator, from 0 for fully random hues to 1 for no randomness
+    #[clap(long, default_value = "0.8", validator = check_unit_interval, help_heading = "COLOURS")]
+    dir_blend: f32,
+
+    /// How much of the test generator to mix into the rand generator if using
+    /// the blend colour generator, from 0 for none to 1 for only the test
+    /// generator
+    #[clap(long, default_value = "0.5", validator = check_unit_interval, help_heading = "COLOURS")]
+    blend_factor: f32,
+
+    /// Distance moved through the noise per pixel if using the noise colour
+    /// generator, so smaller scales give smoother colours
+    #[clap(long, default_value = "0.02", validator = check_non_negative, help_heading = "COLOURS")]
+    noise_scale: f64,
+
+    /// Number of layers of finer and finer detail if using the noise colour
+    /// generator
+    #[clap(long, default_value = "6", validator = check_nonzero, help_heading = "COLOURS")]
+    noise_octaves: u32,
+
+    /// How much weaker each layer of detail is than the last if using the
+    /// noise colour generator, out of 1
+    #[clap(long, default_value = "0.5", validator = check_unit_interval, help_heading = "COLOURS")]
+    noise_persistence: f64,
+
+    /// File of colours for the palette colour generator, either one hex code
+    /// per line or a GIMP palette
+    ///
+    /// Each step moves to a random palette colour within `-D` of the last on
+    /// every channel, or to the nearest other colour if none are that close.
+    #[clap(
+        long,
+        alias = "palette",
+        parse(from_os_str),
+        value_name = "PATH",
+        required_if_eq("colour-gen", "palette"),
+        help_heading = "COLOURS"
+    )]
+    palette_file: Option<std::path::PathBuf>,
+
+    /// Image to sample colours from for the sampled colour generator
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "PATH",
+        required_if_eq("colour-gen", "sampled"),
+        help_heading = "COLOURS"
+    )]
+    palette_image: Option<std::path::PathBuf>,
+
+    /// Largest number of colours to sample from the palette image
+    #[clap(long, default_value = "256", validator = check_palette_size, help_heading = "COLOURS")]
+    palette_size: usize,
+
+    /// Colours read from the palette file or sampled from the palette image
+    #[clap(skip)]
+    palette: Vec<Rgb<u8>>,
 
     /// Seed for random number generator
     ///
-    /// If no seed is specified, will generate a seed using system calls.
-    #[clap(short = 'S', long)]
+    /// If no seed is specified, will generate a seed using system calls, and
+    /// print it on stderr as `seed: <seed>` unless progress is hidden.
+    #[clap(short = 'S', long, help_heading = "SEED")]
     seed: Option<u64>,
 
-    /// Column to start tree at, expressed as coords in 0..1
+    /// Seed for growing the tree only, overriding the main seed
+    ///
+    /// Keep this while changing the main seed to re-roll the colours of the
+    /// same tree.
+    #[clap(long, help_heading = "SEED")]
+    tree_seed: Option<u64>,
+
+    /// Seed for picking colours only, overriding the main seed
+    ///
+    /// Keep this while changing the main seed to colour a different tree in
+    /// the same way.
+    #[clap(long, help_heading = "SEED")]
+    colour_seed: Option<u64>,
+
+    /// Phrase to hash into a seed for the random number generator
+    #[clap(long, conflicts_with = "seed", help_heading = "SEED")]
+    seed_phrase: Option<String>,
+
+    /// Derive the seed from today's UTC date, so everyone gets the same image
+    /// on the same day
+    #[clap(long, conflicts_with_all = &["seed", "seed-phrase"], help_heading = "SEED")]
+    daily: bool,
+
+    /// Extra text mixed into the daily seed, for a community's own image of the
+    /// day
+    #[clap(long, requires = "daily", help_heading = "SEED")]
+    daily_salt: Option<String>,
+
+    /// Column to start tree at, as a fraction of the width in 0..1, where 1 is
+    /// the last column
     #[clap(short = 'X', default_value = "0.0", validator = check_unit_interval, help_heading = "FILL ORDER")]
     x: f64,
 
-    /// Row to start tree at, expressed as coords in 0..1
-    #[clap(short = 'Y', default_value = "0.0", help_heading = "FILL ORDER")]
+    /// Row to start tree at, as a fraction of the height in 0..1, where 1 is
+    /// the last row
+    #[clap(short = 'Y', default_value = "0.0", validator = check_unit_interval, help_heading = "FILL ORDER")]
     y: f64,
+
+    /// Extra point to start a tree at, as `x,y` coords in 0..1
+    ///
+    /// Can be given more than once. The Prim, Wilson and BFS tree generators
+    /// grow a separate tree from each start point, all beginning at the start
+    /// colour, while other generators only use `-X`/`-Y`. Start points that land on the
+    /// same pixel are merged into one.
+    #[clap(
+        long = "start",
+        value_name = "X,Y",
+        parse(try_from_str = parse_start_point),
+        help_heading = "FILL ORDER"
+    )]
+    starts: Vec<(f64, f64)>,
+
+    /// Number of branches waiting in a plotting task before half are shared
+    /// with a new parallel task
+    ///
+    /// Lower values spread work across threads sooner, higher values spawn
+    /// fewer tasks. Does not change the output image.
+    #[clap(long, default_value = "256", validator = check_nonzero)]
+    split_at: usize,
+
+    /// Most children of one pixel that can be shared with other parallel
+    /// tasks
+    ///
+    /// Any further children are coloured by the task that reached the pixel,
+    /// which bounds the number of tasks for trees where a pixel has a huge
+    /// number of children. Does not change the output image.
+    #[clap(long, default_value = "8", validator = check_nonzero)]
+    max_fanout: usize,
+
+    /// Save the tree to a file, to colour it again later with `--load-tree`
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "PATH",
+        help_heading = "FILL ORDER"
+    )]
+    save_tree: Option<std::path::PathBuf>,
+
+    /// Colour a tree saved with `--save-tree` instead of growing a new one
+    ///
+    /// The image must be the same size as when the tree was saved. Pass the
+    /// same start points too, since separate trees grown from several start
+    /// points are only coloured if they have a start point.
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "PATH",
+        help_heading = "FILL ORDER"
+    )]
+    load_tree: Option<std::path::PathBuf>,
+
+    /// Only fill the parts of the image where this mask image is bright
+    ///
+    /// The mask must have the same dimensions as the output, and the start
+    /// points must be inside it.
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "PATH",
+        help_heading = "FILL ORDER"
+    )]
+    mask: Option<std::path::PathBuf>,
+
+    /// Brightness from 0 to 255 that a mask pixel needs to be filled
+    #[clap(
+        long,
+        default_value = "128",
+        requires = "mask",
+        help_heading = "FILL ORDER"
+    )]
+    mask_threshold: u8,
+
+    /// Colour of pixels outside the mask, as a hex code
+    #[clap(
+        long,
+        alias = "mask-bg-color",
+        default_value = "000000",
+        parse(try_from_str = parse_hex_colour),
+        requires = "mask",
+        help_heading = "FILL ORDER"
+    )]
+    mask_bg_colour: Rgb<u8>,
+
+    /// Also write an animation of the fill spreading across the image
+    ///
+    /// Paths ending in `.png` get an animated PNG, and anything else a GIF.
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "PATH",
+        conflicts_with = "no-save",
+        help_heading = "ANIMATION"
+    )]
+    animate: Option<std::path::PathBuf>,
+
+    /// Frames per second of the animation
+    #[clap(
+        long,
+        default_value = "30",
+        requires = "animate",
+        validator = check_nonzero,
+        help_heading = "ANIMATION"
+    )]
+    animate_fps: u32,
+
+    /// Number of frames to split the animation into
+    #[clap(
+        long,
+        default_value = "100",
+        requires = "animate",
+        conflicts_with = "frame-every",
+        validator = check_nonzero,
+        help_heading = "ANIMATION"
+    )]
+    animate_frames: u32,
+
+    /// Number of pixels placed between frames of the animation, instead of
+    /// splitting it into `--animate-frames` frames
+    #[clap(long, requires = "animate", validator = check_nonzero, help_heading = "ANIMATION")]
+    frame_every: Option<u32>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
-enum ColourGen {
-    /// Colours that move linearly through white, yellow, red and black
-    Test,
-    /// A randomly perturbed colour compared to previous colour
-    Rand,
+enum OutputMode {
+    /// The coloured image
+    #[clap(alias = "colors")]
+    Colours,
+    /// The tree drawn as a black and white maze
+    Maze,
+    /// The coloured image, and the maze beside it
+    Both,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
-enum TreeGen {
-    /// A horizontal connection through the middle of the image, vertical connections down every column
-    Test,
-    /// Every pixel connected to two other pixels in a square spiral
-    Spiral,
-    /// Uses Prim's Algorithm to connect all pixels randomly into a tree
-    Prim,
+enum OutputFormat {
+    /// Lossless PNG
+    Png,
+    /// Lossy JPEG
+    #[clap(alias = "jpeg")]
+    Jpg,
+    /// Uncompressed bitmap
+    Bmp,
+    /// Uncompressed TIFF
+    #[clap(alias = "tif")]
+    Tiff,
+}
+
+impl Cli {
+    /// The generator settings given by the arguments
+    fn generator_config(&self) -> GeneratorConfig {
+        let mut config = GeneratorConfig::new(self.width, self.height)
+            .colour_gen(self.colour_gen)
+            .tree_gen(self.tree_gen)
+            .seed(self.seed)
+            .tree_seed(self.tree_seed)
+            .colour_seed(self.colour_seed)
+            .start_colour(self.start_colour)
+            .alpha(self.alpha.then_some(self.start_alpha))
+            .depth(self.depth)
+            .greyscale_output(self.grayscale_output)
+            .connectivity(self.connectivity)
+            .weight_mode(self.weight_mode)
+            .quad_min_size(self.quad_min_size)
+            .tile(self.tile)
+            .supersample(self.supersample)
+            .symmetry(self.symmetry)
+            .step_size(self.step_size)
+            .channel_steps(self.step_r, self.step_g, self.step_b)
+            .wrap_mode(self.wrap_mode)
+            .hsv_steps(self.hue_step, self.sat_step, self.val_step)
+            .gradient(self.gradient_from, self.gradient_to, self.gradient_length)
+            .gradient_bounce(self.gradient_bounce)
+            .saturation_bounds(self.min_saturation, self.max_saturation)
+            .value_bounds(self.min_value, self.max_value)
+            .direction_hue(self.dir_saturation, self.dir_value, self.dir_blend)
+            .blend_factor(self.blend_factor)
+            .noise(self.noise_scale, self.noise_octaves, self.noise_persistence)
+            .palette(self.palette.iter().copied())
+            .start(self.x, self.y)
+            .split_at(self.split_at)
+            .max_fanout(self.max_fanout);
+        for &(x, y) in &self.starts {
+            config = config.extra_start(x, y);
+        }
+        if let Some(path) = &self.start_image {
+            config = config.start_image(path);
+        }
+        if let Some(path) = &self.save_tree {
+            config = config.save_tree(path);
+        }
+        if let Some(path) = &self.load_tree {
+            config = config.load_tree(path);
+        }
+        if let Some(path) = &self.mask {
+            config = config
+                .mask(path)
+                .mask_threshold(self.mask_threshold)
+                .mask_background(self.mask_bg_colour);
+        }
+        if let Some(path) = &self.animate {
+            config = config
+                .animate(path, self.frame_every)
+                .animation_frames(self.animate_frames)
+                .animation_fps(self.animate_fps);
+        }
+        config
+    }
+}
+
+impl OutputFormat {
+    fn encoding(self, jpeg_quality: u8) -> output::Encoding {
+        match self {
+            OutputFormat::Png => output::Encoding::Png {
+                best_compression: false,
+            },
+            OutputFormat::Jpg => output::Encoding::Jpeg {
+                quality: jpeg_quality,
+            },
+            OutputFormat::Bmp => output::Encoding::Bmp,
+            OutputFormat::Tiff => output::Encoding::Tiff,
+        }
+    }
+}
+
+fn parse_start_colour(s: &str) -> Result<StartColour, String> {
+    if s.eq_ignore_ascii_case("random") {
+        return Ok(StartColour::Random);
+    }
+    parse_hex_colour(s).map(StartColour::Fixed)
+}
+
+fn parse_hex_colour(s: &str) -> Result<Rgb<u8>, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 {
+        return Err("hex colour must have exactly 6 digits".to_string());
+    }
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err("hex colour can only contain digits 0-9 and a-f".to_string());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.to_string());
+    Ok(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+fn parse_start_point(s: &str) -> Result<(f64, f64), String> {
+    let (x, y) = s
+        .split_once(',')
+        .ok_or("start point must be two coords separated by a comma")?;
+    let coord = |c: &str| -> Result<f64, String> {
+        check_unit_interval(c.trim())?;
+        c.trim()
+            .parse()
+            .map_err(|_| "not parseable as float".to_string())
+    };
+    Ok((coord(x)?, coord(y)?))
 }
 
 fn check_unit_interval(s: &str) -> Result<(), String> {
     let float: f64 = s.parse().map_err(|_| "not parseable as float")?;
+    if float.is_nan() {
+        return Err("float must be a number".to_string());
+    }
     if float < 0. {
         return Err("float cannot be negative".to_string());
     }
@@ -94,14 +837,489 @@ fn check_unit_interval(s: &str) -> Result<(), String> {
     Ok(())
 }
 
+fn check_non_negative(s: &str) -> Result<(), String> {
+    let float: f32 = s.parse().map_err(|_| "not parseable as float")?;
+    if !float.is_finite() {
+        return Err("float must be finite".to_string());
+    }
+    if float < 0. {
+        return Err("float cannot be negative".to_string());
+    }
+    Ok(())
+}
+
+/// `stderr`, `stdout` or `none`, or else a file path
+fn parse_progress_output(s: &OsStr) -> ProgressOutput {
+    match s.to_str() {
+        Some("stderr") => ProgressOutput::Stderr,
+        Some("stdout") => ProgressOutput::Stdout,
+        Some("none") => ProgressOutput::Hidden,
+        _ => ProgressOutput::File(s.into()),
+    }
+}
+
+fn check_brightness(s: &str) -> Result<(), String> {
+    let int: i16 = s
+        .parse()
+        .map_err(|_| "not parseable as integer from -255 to 255")?;
+    if !(-255..=255).contains(&int) {
+        return Err("brightness must be from -255 to 255".to_string());
+    }
+    Ok(())
+}
+
+fn check_contrast(s: &str) -> Result<(), String> {
+    let float: f32 = s.parse().map_err(|_| "not parseable as float")?;
+    if !float.is_finite() {
+        return Err("float must be finite".to_string());
+    }
+    if float < -100. {
+        return Err("contrast cannot be less than -100".to_string());
+    }
+    Ok(())
+}
+
+fn check_jpeg_quality(s: &str) -> Result<(), String> {
+    let int: u8 = s
+        .parse()
+        .map_err(|_| "not parseable as integer from 1 to 100")?;
+    if !(1..=100).contains(&int) {
+        return Err("JPEG quality must be from 1 to 100".to_string());
+    }
+    Ok(())
+}
+
+fn check_palette_size(s: &str) -> Result<(), String> {
+    let int: usize = s.parse().map_err(|_| "not parseable as integer")?;
+    if !(2..=256).contains(&int) {
+        return Err("palette size must be from 2 to 256".to_string());
+    }
+    Ok(())
+}
+
+fn check_nonzero(s: &str) -> Result<(), String> {
+    let int: usize = s.parse().map_err(|_| "not parseable as integer")?;
+    if int == 0 {
+        return Err("value must be at least 1".to_string());
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
-    // parse command line arguments
-    let args = Cli::parse();
+    let start = Instant::now();
+    // parse command line arguments, leniently at first in case the config
+    // file fills in something required
+    let mut matches = Cli::command().ignore_errors(true).get_matches();
+    if let Some(path) = matches.value_of_os("config") {
+        matches = with_config(Path::new(path), &matches)?;
+    } else {
+        matches = Cli::command().get_matches();
+    }
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if args.dump_config {
+        print!("{}", config_file::dump(&Cli::command(), &matches));
+        return Ok(());
+    }
+    if let Some(path) = &args.from_sidecar {
+        sidecar::Sidecar::read(path)?.apply(&mut args, |id| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        });
+    }
+    match args.colour_gen {
+        ColourGen::Palette => {
+            if let Some(path) = &args.palette_file {
+                args.palette = palette::read(path)?;
+            }
+        }
+        ColourGen::Sampled => {
+            if let Some(path) = &args.palette_image {
+                args.palette = palette::from_image(path, args.palette_size)?;
+            }
+        }
+        _ => (),
+    }
+    if let Some(path) = &args.match_dimensions {
+        let (width, height) = ::image::image_dimensions(path)
+            .with_context(|| format!("Failed to read dimensions of {}", path.display()))?;
+        for (id, size, matched) in [
+            ("width", &mut args.width, width),
+            ("height", &mut args.height, height),
+        ] {
+            if matches.value_source(id) == Some(ValueSource::CommandLine) {
+                eprintln!(
+                    "Using {} {} from the command line rather than {} from {}",
+                    id,
+                    size,
+                    matched,
+                    path.display()
+                );
+            } else {
+                *size = matched;
+            }
+        }
+    }
+    let mut metadata = Vec::new();
+    if args.daily {
+        let date = seed::Date::today_utc();
+        let seed = seed::phrase_seed(&seed::daily_phrase(date, args.daily_salt.as_deref()));
+        if !args.quiet {
+            eprintln!("Daily seed for {}: {}", date, seed);
+        }
+        metadata.push(("Daily", date.to_string()));
+        if let Some(salt) = &args.daily_salt {
+            metadata.push(("Daily salt", salt.clone()));
+        }
+        args.seed = Some(seed);
+    } else if let Some(phrase) = &args.seed_phrase {
+        metadata.push(("Seed phrase", phrase.clone()));
+        args.seed = Some(seed::phrase_seed(phrase));
+    }
+    let verbosity = if args.verbose {
+        Verbosity::verbose(start)
+    } else {
+        Verbosity::silent()
+    };
+    let progress = if args.quiet {
+        ProgressOutput::Hidden
+    } else {
+        args.progress_output.clone()
+    };
+    if progress == ProgressOutput::Stdout && (args.no_save || args.count.is_some()) {
+        bail!("Progress can't go to stdout while the output is written there");
+    }
+    let interrupt = Interrupt::new();
+    ctrl_c::catch(&interrupt);
+    match args.count {
+        Some(count) => batch(args, &metadata, count, progress, verbosity, &interrupt),
+        None => run(args, metadata, progress, verbosity, &interrupt),
+    }
+}
+
+/// Parse the command line again with the options from a config file added,
+/// blaming the line of the file for any option that doesn't parse or whose
+/// step size doesn't suit the colour generator
+fn with_config(path: &Path, matches: &ArgMatches) -> Result<ArgMatches> {
+    let command = Cli::command();
+    let config_args = config_file::Config::read(path)?
+        .args(&command, matches)
+        .with_context(|| format!("Invalid config {}", path.display()))?;
+    let mut args = ::std::env::args_os();
+    let program = args.next().unwrap_or_default();
+    let command_line = args.collect::<Vec<_>>();
+    // options from the file go first, so they can't end up after a `--`
+    let parse = |config_args: &[&OsString]| {
+        Cli::command().try_get_matches_from(
+            ::std::iter::once(&program)
+                .chain(config_args.iter().copied())
+                .chain(&command_line),
+        )
+    };
+    let all_args = config_args.iter().flat_map(|(_, args)| args);
+    let matches = parse(&all_args.collect::<Vec<_>>()).or_else(|e| {
+        // an option is only to blame if it fails differently from the
+        // command line on its own
+        let command_line_error = parse(&[]).err().map(|e| e.kind());
+        for (source, args) in &config_args {
+            let error = parse(&args.iter().collect::<Vec<_>>()).err();
+            if let Some(e) = error.filter(|e| Some(e.kind()) != command_line_error) {
+                let message = e.to_string();
+                let message = message.lines().next().unwrap_or_default();
+                bail!(
+                    "Invalid config {}: {}: {}",
+                    path.display(),
+                    source,
+                    message.trim_start_matches("error: ")
+                );
+            }
+        }
+        e.exit()
+    })?;
+    // values that parse can still clash, like a step size too big for the
+    // colour generator
+    let check = |matches: &ArgMatches| match Cli::from_arg_matches(matches) {
+        Ok(args) => args.generator_config().check_step_sizes(),
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = check(&matches) {
+        if !parse(&[]).is_ok_and(|matches| check(&matches).is_ok()) {
+            // the command line is wrong on its own, so generating will say so
+            return Ok(matches);
+        }
+        for (source, args) in &config_args {
+            let alone = parse(&args.iter().collect::<Vec<_>>());
+            if alone.is_ok_and(|matches| check(&matches).is_err()) {
+                bail!("Invalid config {}: {}: {:#}", path.display(), source, e);
+            }
+        }
+        bail!("Invalid config {}: {:#}", path.display(), e);
+    }
+    Ok(matches)
+}
+
+/// Generate `count` images with consecutive seeds, carrying on past failures
+/// but not past an interrupt
+fn batch(
+    args: Cli,
+    metadata: &[(&'static str, String)],
+    count: u32,
+    progress: ProgressOutput,
+    verbosity: Verbosity,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    let out_path = args
+        .out_path
+        .clone()
+        .context("Batch generation needs an output file")?;
+    let base_seed = args.seed.unwrap_or_else(|| {
+        let seed = ::rand::random();
+        if !args.quiet {
+            eprintln!("Base seed for batch: {}", seed);
+        }
+        seed
+    });
+    let digits = count.to_string().len().max(4);
+    let progress = Progress::to(&progress)?;
+    let bar = progress.styled_bar(
+        count.into(),
+        "Batch",
+        ProgressStyle::default_bar()
+            .progress_chars("## ")
+            .template("[{bar}] {prefix} - {pos}/{len} images, {eta} left - {msg}"),
+    );
+    bar.tick();
+    let mut failures = 0;
+    for i in 1..=count {
+        let seed = base_seed.wrapping_add(u64::from(i - 1));
+        let path = numbered_path(&out_path, i, digits, seed);
+        bar.set_message(path.display().to_string());
+        println!("{}\t{}", path.display(), seed);
+        let mut image_args = args.clone();
+        image_args.seed = Some(seed);
+        image_args.out_path = Some(path.clone());
+        if let Err(e) = run(
+            image_args,
+            metadata.to_vec(),
+            ProgressOutput::Hidden,
+            verbosity,
+            interrupt,
+        ) {
+            let report = format!("Failed to generate {}: {:#}", path.display(), e);
+            if bar.is_hidden() {
+                eprintln!("{}", report);
+            } else {
+                bar.println(report);
+            }
+            failures += 1;
+        }
+        if interrupt.is_stopped() {
+            bar.abandon_with_message("Interrupted");
+            bail!("Interrupted after {} of {} images", i, count);
+        }
+        bar.inc(1);
+    }
+    bar.finish_with_message("Done");
+    if failures > 0 {
+        bail!("{} of {} images failed", failures, count);
+    }
+    Ok(())
+}
+
+/// `<stem>_<index>.<ext>`, with the index zero-padded to `digits` digits
+///
+/// Paths containing `{}` or `{seed}` have the index or seed put there
+/// instead.
+fn numbered_path(path: &Path, index: u32, digits: usize, seed: u64) -> PathBuf {
+    if let Some(pattern) = path
+        .to_str()
+        .filter(|text| text.contains("{}") || text.contains("{seed}"))
+    {
+        return pattern
+            .replace("{}", &format!("{:0digits$}", index))
+            .replace("{seed}", &seed.to_string())
+            .into();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:0digits$}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}_{:0digits$}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// `<stem>_maze.<ext>`, for the maze saved beside an image
+fn maze_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_maze.{}", stem, ext.to_string_lossy()),
+        None => format!("{}_maze", stem),
+    };
+    path.with_file_name(name)
+}
+
+/// `<stem>.partial.<ext>`, for an image saved before it was finished
+fn partial_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.partial.{}", stem, ext.to_string_lossy()),
+        None => format!("{}.partial", stem),
+    };
+    path.with_file_name(name)
+}
+
+/// Generate and save one image, reporting progress to `progress`
+///
+/// If `interrupt` stops the image while its pixels are being placed, the
+/// unfinished image is saved to a `.partial` path instead, then an error is
+/// returned.
+fn run(
+    mut args: Cli,
+    mut metadata: Vec<(&'static str, String)>,
+    progress: ProgressOutput,
+    verbosity: Verbosity,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    let start = Instant::now();
+    if args.sidecar && args.seed.is_none() {
+        args.seed = Some(::rand::random());
+    }
+    if let Some(seed) = args.seed {
+        metadata.push(("Seed", seed.to_string()));
+    }
+    if let Some(seed) = args.tree_seed {
+        metadata.push(("Tree seed", seed.to_string()));
+    }
+    if let Some(seed) = args.colour_seed {
+        metadata.push(("Colour seed", seed.to_string()));
+    }
+    let sidecar = args
+        .sidecar
+        .then(|| sidecar::Sidecar::from_args(&args, args.seed.unwrap_or_default()));
     let no_save = args.no_save;
+    let max_bytes = args.max_bytes;
+    let format = args.format;
+    let jpeg_quality = args.jpeg_quality;
+    let adjustments = output::Adjustments {
+        invert: args.invert,
+        brightness: args.brightness,
+        contrast: args.contrast,
+    };
     let out_path = args.out_path.clone();
-    let buf = new_image(args).context("Failed to generate image")?;
-    if !no_save {
-        buf.save(out_path).context("Failed to write output file")?;
+    let options = output::Options {
+        metadata: &metadata,
+        background: args.background,
+    };
+    let mut config = args
+        .generator_config()
+        .progress_output(progress)
+        .verbosity(verbosity)
+        .print_stats(args.print_stats)
+        .interrupt(interrupt.clone());
+    let (mut buf, timings) = match args.output_mode {
+        OutputMode::Colours => generate_timed(&config).context("Failed to generate image")?,
+        OutputMode::Maze => {
+            let (maze, timings) =
+                generate_maze_timed(&config).context("Failed to generate maze")?;
+            (DynamicImage::ImageLuma8(maze), timings)
+        }
+        OutputMode::Both => {
+            let path = out_path
+                .as_deref()
+                .context("Saving both the image and the maze needs an output file")?;
+            config = config.save_maze(maze_path(path));
+            generate_timed(&config).context("Failed to generate image")?
+        }
+    };
+    let saving = Instant::now();
+    let interrupted = interrupt.is_stopped();
+    let out_path = if interrupted {
+        out_path.as_deref().map(partial_path)
+    } else {
+        out_path
+    };
+    adjustments.apply(&mut buf);
+    if let Some(out_path) = &out_path {
+        let encoding = match format {
+            Some(format) => Some(format.encoding(jpeg_quality)),
+            None => output::Encoding::from_extension(out_path, jpeg_quality),
+        };
+        match max_bytes {
+            Some(max_bytes) => {
+                let jpeg_only = matches!(encoding, Some(output::Encoding::Jpeg { .. }));
+                let (path, encoding) =
+                    output::save_within(&buf, out_path, max_bytes, jpeg_only, options)
+                        .context("Failed to fit output file within byte budget")?;
+                verbosity.log(format_args!("Saved {} as {}", path.display(), encoding));
+            }
+            None => {
+                output::save(&buf, out_path, encoding, options)
+                    .context("Failed to write output file")?;
+                verbosity.log(format_args!("Saved {}", out_path.display()));
+            }
+        }
+        if let Some(sidecar) = sidecar {
+            let path = sidecar.write_beside(out_path)?;
+            verbosity.log(format_args!("Saved parameters to {}", path.display()));
+        }
+    }
+    if no_save {
+        // Rust never translates newlines on stdout, so binary data passes through
+        // untouched on every platform
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        let format = format.unwrap_or(OutputFormat::Png);
+        match max_bytes {
+            Some(max_bytes) => {
+                let (bytes, encoding) =
+                    output::encode_within(&buf, max_bytes, format == OutputFormat::Jpg, options)
+                        .context("Failed to fit output within byte budget")?;
+                verbosity.log(format_args!("Encoded as {}", encoding));
+                stdout
+                    .write_all(&bytes)
+                    .context("Failed to write image to stdout")?;
+            }
+            None => output::encode_to(&mut stdout, &buf, format.encoding(jpeg_quality), options)
+                .context("Failed to write image to stdout")?,
+        }
+        stdout.flush().context("Failed to flush stdout")?;
+    }
+    if interrupted {
+        match &out_path {
+            Some(path) => bail!(
+                "Interrupted; saved the pixels placed so far to {}",
+                path.display()
+            ),
+            None => bail!("Interrupted; wrote the pixels placed so far to stdout"),
+        }
+    }
+    if args.timing {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.;
+        eprintln!(
+            r#"{{"tree_gen_ms": {:.3}, "prune_ms": {:.3}, "colour_ms": {:.3}, "save_ms": {:.3}, "total_ms": {:.3}}}"#,
+            millis(timings.tree_gen),
+            millis(timings.prune),
+            millis(timings.colour),
+            millis(saving.elapsed()),
+            millis(start.elapsed())
+        );
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colours_need_six_hex_digits() {
+        for bad in ["FFF", "FF80401", "GG0000", "#FFF", "", "#"] {
+            assert!(parse_hex_colour(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn hex_colours_may_start_with_a_hash() {
+        let colour = Rgb([0xFF, 0x80, 0x40]);
+        assert_eq!(parse_hex_colour("#FF8040"), Ok(colour));
+        assert_eq!(parse_hex_colour("FF8040"), Ok(colour));
+        assert_eq!(parse_hex_colour("ff8040"), Ok(colour));
+    }
+}
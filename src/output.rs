@@ -0,0 +1,490 @@
+use ::anyhow::{bail, ensure, Context, Result};
+use ::color_quant::NeuQuant;
+use ::image::{
+    codecs::{bmp::BmpEncoder, jpeg::JpegEncoder, tiff::TiffEncoder},
+    ColorType, DynamicImage, GenericImageView, ImageFormat, Rgb, RgbImage,
+};
+use ::std::{
+    fmt, fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+/// An explicit choice of encoder and its settings
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Encoding {
+    /// Truecolour PNG, optionally trading speed for smaller output
+    Png { best_compression: bool },
+    /// PNG quantized down to a palette with this many colours
+    PalettePng { colours: usize },
+    /// Lossy JPEG with quality in 1..=100
+    Jpeg { quality: u8 },
+    /// Uncompressed bitmap
+    Bmp,
+    /// Uncompressed TIFF
+    Tiff,
+}
+
+impl Encoding {
+    /// File extension appropriate for this encoding
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Encoding::Png { .. } | Encoding::PalettePng { .. } => "png",
+            Encoding::Jpeg { .. } => "jpg",
+            Encoding::Bmp => "bmp",
+            Encoding::Tiff => "tiff",
+        }
+    }
+
+    /// The encoding a path's extension asks for, if it's one of the formats
+    /// that can be chosen explicitly
+    pub(crate) fn from_extension(path: &Path, jpeg_quality: u8) -> Option<Encoding> {
+        if has_extension(path, &["png"]) {
+            Some(Encoding::Png {
+                best_compression: false,
+            })
+        } else if has_extension(path, &["jpg", "jpeg"]) {
+            Some(Encoding::Jpeg {
+                quality: jpeg_quality,
+            })
+        } else if has_extension(path, &["bmp"]) {
+            Some(Encoding::Bmp)
+        } else if has_extension(path, &["tif", "tiff"]) {
+            Some(Encoding::Tiff)
+        } else {
+            None
+        }
+    }
+
+    /// Encodings to try in order when fitting an image into a byte budget
+    ///
+    /// Lossless PNG comes first, then stronger PNG compression, then palette
+    /// quantization with shrinking palettes, then JPEG with decreasing quality.
+    /// If JPEG output was asked for, only the JPEG steps are tried.
+    fn fallbacks(jpeg_only: bool) -> Vec<Encoding> {
+        let mut encodings = Vec::new();
+        if !jpeg_only {
+            encodings.push(Encoding::Png {
+                best_compression: false,
+            });
+            encodings.push(Encoding::Png {
+                best_compression: true,
+            });
+            for colours in [256, 128, 64, 32, 16] {
+                encodings.push(Encoding::PalettePng { colours });
+            }
+        }
+        for quality in (10..=90).rev().step_by(10) {
+            encodings.push(Encoding::Jpeg { quality });
+        }
+        encodings
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Png {
+                best_compression: false,
+            } => write!(f, "PNG"),
+            Encoding::Png {
+                best_compression: true,
+            } => write!(f, "PNG (best compression)"),
+            Encoding::PalettePng { colours } => write!(f, "PNG ({} colour palette)", colours),
+            Encoding::Jpeg { quality } => write!(f, "JPEG (quality {})", quality),
+            Encoding::Bmp => write!(f, "BMP"),
+            Encoding::Tiff => write!(f, "TIFF"),
+        }
+    }
+}
+
+/// Settings shared by every encoding
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct Options<'a> {
+    /// Text to embed as PNG `tEXt` chunks
+    pub(crate) metadata: &'a [(&'a str, String)],
+    /// Colour to flatten transparent images onto for formats without alpha
+    pub(crate) background: Option<Rgb<u8>>,
+}
+
+/// Changes to the colours of the finished image before it's encoded
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub(crate) struct Adjustments {
+    pub(crate) invert: bool,
+    /// Added to every colour channel, in 8-bit steps even for 16-bit images
+    pub(crate) brightness: i16,
+    /// Percentage to stretch colours away from mid-grey by, or squash them
+    /// towards it if negative
+    pub(crate) contrast: f32,
+}
+
+impl Adjustments {
+    /// Invert, then brighten, then adjust contrast, leaving alpha alone
+    pub(crate) fn apply(self, buf: &mut DynamicImage) {
+        if self.invert {
+            buf.invert();
+        }
+        if self.brightness != 0 {
+            let scale = if is_16_bit(buf) { 257 } else { 1 };
+            *buf = buf.brighten(i32::from(self.brightness) * scale);
+        }
+        if self.contrast != 0. {
+            *buf = buf.adjust_contrast(self.contrast);
+        }
+    }
+}
+
+/// Save an image, embedding text metadata if the format supports it
+///
+/// Without an explicit encoding, the format is guessed from the extension.
+/// Metadata is written as PNG `tEXt` chunks; other formats are saved without
+/// it. Transparent images saved as JPEG are flattened onto the background, and
+/// 16-bit images can't be saved as JPEG at all.
+pub(crate) fn save(
+    buf: &DynamicImage,
+    path: &Path,
+    encoding: Option<Encoding>,
+    options: Options,
+) -> Result<()> {
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        // formats that can't be picked explicitly still save by extension
+        None if ImageFormat::from_path(path).is_ok() => {
+            return buf.save(path).context("Failed to encode image");
+        }
+        None => bail!(
+            "Can't tell what format to save {} in; give it an extension or pass --format with one of png, jpg, bmp or tiff",
+            path.display()
+        ),
+    };
+    let bytes = encode(buf, encoding, options)?;
+    fs::write(path, bytes).context("Failed to write image to file")
+}
+
+/// Save an image in the best encoding that fits within `max_bytes`
+///
+/// The extension of `path` is replaced to match the chosen encoding, and the
+/// final path and encoding are returned.
+pub(crate) fn save_within(
+    buf: &DynamicImage,
+    path: &Path,
+    max_bytes: usize,
+    jpeg_only: bool,
+    options: Options,
+) -> Result<(PathBuf, Encoding)> {
+    let (bytes, encoding) = encode_within(buf, max_bytes, jpeg_only, options)?;
+    let path = path.with_extension(encoding.extension());
+    fs::write(&path, bytes).context("Failed to write output file")?;
+    Ok((path, encoding))
+}
+
+/// Encode an image in the best encoding that fits within `max_bytes`
+///
+/// Fails if even the smallest encoding is over budget. JPEG is never tried for
+/// 16-bit images, and is only tried for transparent images if there is a
+/// background to flatten them onto.
+pub(crate) fn encode_within(
+    buf: &DynamicImage,
+    max_bytes: usize,
+    jpeg_only: bool,
+    options: Options,
+) -> Result<(Vec<u8>, Encoding)> {
+    let mut smallest = None;
+    let lossy_allowed =
+        !is_16_bit(buf) && (!buf.color().has_alpha() || options.background.is_some());
+    for encoding in Encoding::fallbacks(jpeg_only) {
+        if let Encoding::Jpeg { .. } = encoding {
+            if !lossy_allowed {
+                continue;
+            }
+        }
+        let bytes = encode(buf, encoding, options)
+            .with_context(|| format!("Failed to encode image as {}", encoding))?;
+        if bytes.len() <= max_bytes {
+            return Ok((bytes, encoding));
+        }
+        smallest = Some(bytes.len());
+    }
+    match smallest {
+        Some(len) => bail!(
+            "No encoding fits within {} bytes; the smallest attempt was {} bytes",
+            max_bytes,
+            len
+        ),
+        None => bail!("No encodings available to try"),
+    }
+}
+
+/// Encode an image into memory with an explicit encoder
+pub(crate) fn encode(buf: &DynamicImage, encoding: Encoding, options: Options) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    encode_to(&mut bytes, buf, encoding, options)?;
+    Ok(bytes)
+}
+
+/// Encode an image with an explicit encoder, streaming it into a writer
+pub(crate) fn encode_to<W: Write>(
+    mut writer: W,
+    buf: &DynamicImage,
+    encoding: Encoding,
+    options: Options,
+) -> Result<()> {
+    let metadata = options.metadata;
+    let has_alpha = buf.color().has_alpha();
+    match encoding {
+        Encoding::Png { best_compression } => {
+            let mut encoder = ::png::Encoder::new(writer, buf.width(), buf.height());
+            encoder.set_color(match (buf.color().has_color(), has_alpha) {
+                (true, true) => ::png::ColorType::RGBA,
+                (true, false) => ::png::ColorType::RGB,
+                (false, true) => ::png::ColorType::GrayscaleAlpha,
+                (false, false) => ::png::ColorType::Grayscale,
+            });
+            encoder.set_compression(if best_compression {
+                ::png::Compression::Best
+            } else {
+                ::png::Compression::Default
+            });
+            if is_16_bit(buf) {
+                // PNG stores 16-bit samples big-endian
+                encoder.set_depth(::png::BitDepth::Sixteen);
+                let samples = match buf {
+                    DynamicImage::ImageLuma16(buf) => buf.as_raw(),
+                    DynamicImage::ImageLumaA16(buf) => buf.as_raw(),
+                    DynamicImage::ImageRgb16(buf) => buf.as_raw(),
+                    DynamicImage::ImageRgba16(buf) => buf.as_raw(),
+                    _ => bail!("Unsupported 16-bit pixel type"),
+                };
+                let data = samples
+                    .iter()
+                    .flat_map(|sample| sample.to_be_bytes())
+                    .collect::<Vec<_>>();
+                write_png(encoder, &data, metadata)?;
+            } else {
+                encoder.set_depth(::png::BitDepth::Eight);
+                write_png(encoder, buf.as_bytes(), metadata)?;
+            }
+        }
+        Encoding::PalettePng { colours } => {
+            let rgba = buf.to_rgba8().into_raw();
+            let quantizer = NeuQuant::new(10, colours, &rgba);
+            let indices = rgba
+                .chunks_exact(4)
+                .map(|pixel| quantizer.index_of(pixel) as u8)
+                .collect::<Vec<_>>();
+            let mut encoder = ::png::Encoder::new(writer, buf.width(), buf.height());
+            encoder.set_color(::png::ColorType::Indexed);
+            encoder.set_depth(::png::BitDepth::Eight);
+            encoder.set_compression(::png::Compression::Best);
+            encoder.set_palette(quantizer.color_map_rgb());
+            if has_alpha {
+                let palette = quantizer.color_map_rgba();
+                encoder.set_trns(
+                    palette
+                        .chunks_exact(4)
+                        .map(|rgba| rgba[3])
+                        .collect::<Vec<_>>(),
+                );
+            }
+            write_png(encoder, &indices, metadata)?;
+        }
+        Encoding::Bmp => {
+            ensure!(
+                !is_16_bit(buf),
+                "BMP only supports 8 bits per channel; save 16-bit images as PNG or TIFF instead"
+            );
+            BmpEncoder::new(&mut writer)
+                .encode(buf.as_bytes(), buf.width(), buf.height(), buf.color())
+                .context("Failed to encode BMP")?;
+        }
+        Encoding::Tiff => {
+            // TIFF offsets are written after the data, so it needs seeking
+            let mut bytes = Cursor::new(Vec::new());
+            TiffEncoder::new(&mut bytes)
+                .encode(buf.as_bytes(), buf.width(), buf.height(), buf.color())
+                .context("Failed to encode TIFF")?;
+            writer
+                .write_all(bytes.get_ref())
+                .context("Failed to write TIFF")?;
+        }
+        Encoding::Jpeg { quality } => {
+            if let DynamicImage::ImageLuma8(luma) = buf {
+                return JpegEncoder::new_with_quality(&mut writer, quality)
+                    .encode(luma, luma.width(), luma.height(), ColorType::L8)
+                    .context("Failed to encode JPEG");
+            }
+            let flattened;
+            let rgb = match buf {
+                DynamicImage::ImageRgb8(rgb) => rgb,
+                _ => {
+                    flattened = flatten(buf, options.background)?;
+                    &flattened
+                }
+            };
+            JpegEncoder::new_with_quality(&mut writer, quality)
+                .encode(rgb, rgb.width(), rgb.height(), ColorType::Rgb8)
+                .context("Failed to encode JPEG")?;
+        }
+    }
+    Ok(())
+}
+
+/// Composite an image over an opaque background colour
+///
+/// Fails if the image is transparent and no background was given, since
+/// silently dropping the alpha channel would change how the image looks.
+fn flatten(buf: &DynamicImage, background: Option<Rgb<u8>>) -> Result<RgbImage> {
+    ensure!(
+        !is_16_bit(buf),
+        "JPEG only supports 8 bits per channel; save 16-bit images as PNG instead"
+    );
+    if !buf.color().has_alpha() {
+        return Ok(buf.to_rgb8());
+    }
+    let Rgb(background) = background.context(
+        "JPEG cannot store transparency; pass --background to flatten the image onto a colour",
+    )?;
+    let rgba = buf.to_rgba8();
+    let flattened = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = u16::from(pixel[3]);
+        let mut channels = [0; 3];
+        for ((out, &fg), &bg) in channels.iter_mut().zip(&pixel.0[..3]).zip(&background) {
+            *out = ((u16::from(fg) * alpha + u16::from(bg) * (255 - alpha) + 127) / 255) as u8;
+        }
+        Rgb(channels)
+    });
+    Ok(flattened)
+}
+
+/// Write the header, `tEXt` metadata chunks and image data of a PNG
+fn write_png<W: Write>(
+    encoder: ::png::Encoder<W>,
+    data: &[u8],
+    metadata: &[(&str, String)],
+) -> Result<()> {
+    let mut writer = encoder
+        .write_header()
+        .context("Failed to write PNG header")?;
+    for (keyword, text) in metadata {
+        let mut chunk = Vec::with_capacity(keyword.len() + 1 + text.len());
+        chunk.extend_from_slice(keyword.as_bytes());
+        chunk.push(0);
+        chunk.extend_from_slice(text.as_bytes());
+        writer
+            .write_chunk(*b"tEXt", &chunk)
+            .context("Failed to write PNG metadata")?;
+    }
+    writer
+        .write_image_data(data)
+        .context("Failed to write PNG image data")
+}
+
+/// Whether an image has 16 bits per channel
+fn is_16_bit(buf: &DynamicImage) -> bool {
+    matches!(
+        buf,
+        DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+    )
+}
+
+/// Whether a path has one of the given extensions, ignoring case
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::lapidary::{generate, GeneratorConfig};
+
+    fn image() -> DynamicImage {
+        let config = GeneratorConfig::new(40, 30).seed(3).with_progress(false);
+        DynamicImage::ImageRgb8(generate(&config).unwrap())
+    }
+
+    /// Check the bytes are in the given format and decode to a 40x30 image
+    fn assert_decodes(bytes: &[u8], format: ImageFormat) {
+        assert_eq!(::image::guess_format(bytes).unwrap(), format);
+        let decoded = ::image::load_from_memory_with_format(bytes, format).unwrap();
+        assert_eq!(decoded.dimensions(), (40, 30));
+    }
+
+    #[test]
+    fn encode_writes_the_chosen_format() {
+        let buf = image();
+        for (encoding, format) in [
+            (
+                Encoding::Png {
+                    best_compression: false,
+                },
+                ImageFormat::Png,
+            ),
+            (
+                Encoding::Png {
+                    best_compression: true,
+                },
+                ImageFormat::Png,
+            ),
+            (Encoding::PalettePng { colours: 16 }, ImageFormat::Png),
+            (Encoding::Jpeg { quality: 80 }, ImageFormat::Jpeg),
+            (Encoding::Bmp, ImageFormat::Bmp),
+            (Encoding::Tiff, ImageFormat::Tiff),
+        ] {
+            let bytes = encode(&buf, encoding, Options::default()).unwrap();
+            assert_decodes(&bytes, format);
+        }
+    }
+
+    #[test]
+    fn encode_within_prefers_lossless_png() {
+        let (bytes, encoding) =
+            encode_within(&image(), usize::MAX, false, Options::default()).unwrap();
+        assert_eq!(
+            encoding,
+            Encoding::Png {
+                best_compression: false
+            }
+        );
+        assert_decodes(&bytes, ImageFormat::Png);
+    }
+
+    #[test]
+    fn encode_within_falls_back_to_fit_the_budget() {
+        let buf = image();
+        let png = encode(
+            &buf,
+            Encoding::Png {
+                best_compression: true,
+            },
+            Options::default(),
+        );
+        let budget = png.unwrap().len() - 1;
+        let (bytes, encoding) = encode_within(&buf, budget, false, Options::default()).unwrap();
+        assert!(bytes.len() <= budget);
+        assert!(!matches!(encoding, Encoding::Png { .. }), "{}", encoding);
+        let format = match encoding {
+            Encoding::Jpeg { .. } => ImageFormat::Jpeg,
+            _ => ImageFormat::Png,
+        };
+        assert_decodes(&bytes, format);
+    }
+
+    #[test]
+    fn encode_within_only_tries_jpeg_when_asked() {
+        let (bytes, encoding) =
+            encode_within(&image(), usize::MAX, true, Options::default()).unwrap();
+        assert_eq!(encoding, Encoding::Jpeg { quality: 90 });
+        assert_decodes(&bytes, ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn encode_within_fails_when_nothing_fits() {
+        assert!(encode_within(&image(), 10, false, Options::default()).is_err());
+    }
+}
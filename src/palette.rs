@@ -0,0 +1,122 @@
+use super::parse_hex_colour;
+use ::anyhow::{ensure, Context, Result};
+use ::color_quant::NeuQuant;
+use ::image::Rgb;
+use ::std::{collections::HashSet, fs, path::Path};
+
+/// Read a palette file with one colour per line
+///
+/// Accepts GIMP's `.gpl` format, Lospec's `.hex` format, with or without a
+/// leading `#`, and Paint.NET's `.txt` format, whose `AARRGGBB` colours have
+/// their alpha dropped. Blank lines and comments are skipped.
+pub(crate) fn read(path: &Path) -> Result<Vec<Rgb<u8>>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read palette {}", path.display()))?;
+    parse(&text).with_context(|| format!("Invalid palette {}", path.display()))
+}
+
+fn parse(text: &str) -> Result<Vec<Rgb<u8>>> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    let gimp = lines
+        .clone()
+        .next()
+        .is_some_and(|(_, line)| line == "GIMP Palette");
+    let mut colours = Vec::new();
+    if gimp {
+        lines.next();
+    }
+    for (number, line) in lines {
+        // a `#` on its own or followed by a space starts a comment, rather
+        // than a hex colour
+        if line.starts_with(';') || line.starts_with("//") || line == "#" || line.starts_with("# ")
+        {
+            continue;
+        }
+        let colour = if gimp {
+            if line.starts_with('#') || line.contains(':') {
+                // comments and header fields like `Name:` and `Columns:`
+                continue;
+            }
+            parse_gimp_colour(line)
+        } else {
+            parse_hex_line(line)
+        };
+        colours.push(colour.with_context(|| format!("Line {}: `{}`", number, line))?);
+    }
+    ensure!(!colours.is_empty(), "Palette has no colours");
+    ensure!(
+        colours.iter().any(|&colour| colour != colours[0]),
+        "Palette needs at least 2 distinct colours"
+    );
+    Ok(colours)
+}
+
+/// A hex colour, possibly with Paint.NET's alpha first
+fn parse_hex_line(line: &str) -> Result<Rgb<u8>> {
+    let hex = line.strip_prefix('#').unwrap_or(line);
+    let hex = match hex.get(2..) {
+        Some(rgb) if hex.len() == 8 => rgb,
+        _ => hex,
+    };
+    parse_hex_colour(hex)
+        .map_err(::anyhow::Error::msg)
+        .context("Not a hex colour")
+}
+
+/// Red, green and blue from 0 to 255 separated by whitespace, followed by an
+/// optional name
+fn parse_gimp_colour(line: &str) -> Result<Rgb<u8>> {
+    let mut channels = line.split_whitespace();
+    let mut channel = |name: &str| -> Result<u8> {
+        let channel = channels
+            .next()
+            .with_context(|| format!("Missing {} channel", name))?;
+        channel
+            .parse()
+            .with_context(|| format!("{} channel `{}` isn't from 0 to 255", name, channel))
+    };
+    Ok(Rgb([channel("Red")?, channel("Green")?, channel("Blue")?]))
+}
+
+/// Pick up to `size` colours representative of an image
+///
+/// Images with few enough colours keep them exactly; otherwise the colours
+/// are reduced by NeuQuant, like palette PNG output.
+pub(crate) fn from_image(path: &Path, size: usize) -> Result<Vec<Rgb<u8>>> {
+    let image = ::image::open(path)
+        .with_context(|| format!("Failed to read palette image {}", path.display()))?
+        .into_rgb8();
+    let mut distinct = HashSet::new();
+    for &pixel in image.pixels() {
+        distinct.insert(pixel);
+        if distinct.len() > size {
+            break;
+        }
+    }
+    ensure!(
+        distinct.len() >= 2,
+        "Palette image {} needs at least 2 distinct colours",
+        path.display()
+    );
+    if distinct.len() <= size {
+        return Ok(image
+            .pixels()
+            .copied()
+            .filter(|pixel| distinct.remove(pixel))
+            .collect());
+    }
+    let rgba = image
+        .pixels()
+        .flat_map(|&Rgb([r, g, b])| [r, g, b, u8::MAX])
+        .collect::<Vec<_>>();
+    let quantizer = NeuQuant::new(10, size, &rgba);
+    Ok(quantizer
+        .color_map_rgb()
+        .chunks_exact(3)
+        .map(|rgb| Rgb([rgb[0], rgb[1], rgb[2]]))
+        .collect())
+}
@@ -0,0 +1,112 @@
+use ::std::{
+    fmt,
+    hash::Hasher,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A calendar date in the proleptic Gregorian calendar
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct Date {
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+}
+
+impl Date {
+    /// Today's date in UTC according to the system clock
+    pub(crate) fn today_utc() -> Date {
+        let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(before_epoch) => -(before_epoch.duration().as_secs() as i64),
+        };
+        Date::from_unix_days(seconds.div_euclid(86_400))
+    }
+
+    /// Convert a count of days since 1970-01-01 into a calendar date
+    ///
+    /// Uses Howard Hinnant's `civil_from_days` algorithm so the result doesn't
+    /// depend on the platform's time zone database.
+    pub(crate) fn from_unix_days(days: i64) -> Date {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date { year, month, day }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Hash an arbitrary phrase into a seed for the random number generator
+///
+/// Uses SipHash-2-4 with zero keys, which is stable across platforms and
+/// compiler versions, so the same phrase always gives the same image.
+pub(crate) fn phrase_seed(phrase: &str) -> u64 {
+    #[allow(deprecated)]
+    let mut hasher = ::std::hash::SipHasher::new();
+    hasher.write(phrase.as_bytes());
+    hasher.finish()
+}
+
+/// The phrase that is hashed to get the seed for a given day
+pub(crate) fn daily_phrase(date: Date, salt: Option<&str>) -> String {
+    match salt {
+        Some(salt) => format!("{}{}", date, salt),
+        None => date.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i64, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn unix_days_start_at_the_epoch() {
+        assert_eq!(Date::from_unix_days(0), date(1970, 1, 1));
+        assert_eq!(Date::from_unix_days(31), date(1970, 2, 1));
+        assert_eq!(Date::from_unix_days(365), date(1971, 1, 1));
+    }
+
+    #[test]
+    fn unix_days_reach_leap_days() {
+        assert_eq!(Date::from_unix_days(11_016), date(2000, 2, 29));
+        assert_eq!(Date::from_unix_days(19_782), date(2024, 2, 29));
+        assert_eq!(Date::from_unix_days(19_783), date(2024, 3, 1));
+    }
+
+    #[test]
+    fn negative_unix_days_are_before_the_epoch() {
+        assert_eq!(Date::from_unix_days(-1), date(1969, 12, 31));
+        assert_eq!(Date::from_unix_days(-365), date(1969, 1, 1));
+        assert_eq!(Date::from_unix_days(-719_468), date(0, 3, 1));
+    }
+
+    #[test]
+    fn daily_seed_depends_only_on_date_and_salt() {
+        let day = date(2024, 2, 29);
+        let seed = |date, salt| phrase_seed(&daily_phrase(date, salt));
+        assert_eq!(seed(day, Some("salt")), seed(day, Some("salt")));
+        assert_eq!(seed(day, None), seed(day, None));
+        assert_ne!(seed(day, Some("salt")), seed(day, None));
+        assert_ne!(seed(day, None), seed(date(2024, 3, 1), None));
+        assert_eq!(daily_phrase(day, Some("salt")), "2024-02-29salt");
+    }
+}
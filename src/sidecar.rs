@@ -0,0 +1,382 @@
+use super::{Cli, ColourGen, TreeGen};
+use ::anyhow::{bail, ensure, Context, Result};
+use ::clap::ArgEnum;
+use ::std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The generation parameters needed to reproduce an image
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Sidecar {
+    pub(crate) version: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) colour_gen: ColourGen,
+    pub(crate) tree_gen: TreeGen,
+    pub(crate) step_size: u16,
+    pub(crate) seed: u64,
+    pub(crate) tree_seed: Option<u64>,
+    pub(crate) colour_seed: Option<u64>,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+impl Sidecar {
+    /// Record the parameters of a run whose seed has already been resolved
+    pub(crate) fn from_args(args: &Cli, seed: u64) -> Sidecar {
+        Sidecar {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            width: args.width,
+            height: args.height,
+            colour_gen: args.colour_gen,
+            tree_gen: args.tree_gen,
+            step_size: args.step_size,
+            seed,
+            tree_seed: args.tree_seed,
+            colour_seed: args.colour_seed,
+            x: args.x,
+            y: args.y,
+        }
+    }
+
+    /// Overwrite the arguments that weren't given on the command line
+    ///
+    /// `from_command_line` reports whether the argument with a given ID was
+    /// given explicitly. The sidecar's seed is skipped if any other way of
+    /// choosing a seed was used.
+    pub(crate) fn apply(&self, args: &mut Cli, from_command_line: impl Fn(&str) -> bool) {
+        if !from_command_line("width") {
+            args.width = self.width;
+        }
+        if !from_command_line("height") {
+            args.height = self.height;
+        }
+        if !from_command_line("colour-gen") {
+            args.colour_gen = self.colour_gen;
+        }
+        if !from_command_line("tree-gen") {
+            args.tree_gen = self.tree_gen;
+        }
+        if !from_command_line("step-size") {
+            args.step_size = self.step_size;
+        }
+        if !["seed", "seed-phrase", "daily"]
+            .into_iter()
+            .any(&from_command_line)
+        {
+            args.seed = Some(self.seed);
+        }
+        if !from_command_line("tree-seed") {
+            args.tree_seed = self.tree_seed;
+        }
+        if !from_command_line("colour-seed") {
+            args.colour_seed = self.colour_seed;
+        }
+        if !from_command_line("x") {
+            args.x = self.x;
+        }
+        if !from_command_line("y") {
+            args.y = self.y;
+        }
+    }
+
+    /// Serialize as a flat JSON object
+    pub(crate) fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        let mut fields = vec![
+            ("version", json_string(&self.version)),
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+            ("colour_gen", json_string(arg_name(self.colour_gen))),
+            ("tree_gen", json_string(arg_name(self.tree_gen))),
+            ("step_size", self.step_size.to_string()),
+            ("seed", self.seed.to_string()),
+            ("x", format!("{:?}", self.x)),
+            ("y", format!("{:?}", self.y)),
+        ];
+        // only written when given, so older versions can still read the file
+        if let Some(seed) = self.tree_seed {
+            fields.push(("tree_seed", seed.to_string()));
+        }
+        if let Some(seed) = self.colour_seed {
+            fields.push(("colour_seed", seed.to_string()));
+        }
+        for (i, (key, value)) in fields.iter().enumerate() {
+            let comma = if i + 1 < fields.len() { "," } else { "" };
+            let _ = writeln!(json, "  {}: {}{}", json_string(key), value, comma);
+        }
+        json.push_str("}\n");
+        json
+    }
+
+    /// Parse a sidecar written by [`Sidecar::to_json`]
+    ///
+    /// Unknown keys are ignored, so sidecars from newer versions can still be
+    /// read, but every known key must be present apart from the separate tree
+    /// and colour seeds.
+    pub(crate) fn from_json(json: &str) -> Result<Sidecar> {
+        let fields = parse_flat_object(json)?;
+        let get = |key: &str| {
+            fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v)
+                .with_context(|| format!("Sidecar is missing `{}`", key))
+        };
+        let number = |key: &str| -> Result<&str> {
+            match get(key)? {
+                Value::Number(n) => Ok(n),
+                Value::String(_) => bail!("Sidecar field `{}` should be a number", key),
+            }
+        };
+        let string = |key: &str| -> Result<&str> {
+            match get(key)? {
+                Value::String(s) => Ok(s),
+                Value::Number(_) => bail!("Sidecar field `{}` should be a string", key),
+            }
+        };
+        let invalid = |key: &str| format!("Sidecar field `{}` is out of range", key);
+        let optional_seed = |key: &str| -> Result<Option<u64>> {
+            if fields.iter().any(|(k, _)| k == key) {
+                Ok(Some(number(key)?.parse().with_context(|| invalid(key))?))
+            } else {
+                Ok(None)
+            }
+        };
+        let sidecar = Sidecar {
+            version: string("version")?.to_string(),
+            width: number("width")?.parse().with_context(|| invalid("width"))?,
+            height: number("height")?
+                .parse()
+                .with_context(|| invalid("height"))?,
+            colour_gen: ColourGen::from_str(string("colour_gen")?, true)
+                .map_err(::anyhow::Error::msg)
+                .context("Sidecar has an unknown colour generator")?,
+            tree_gen: TreeGen::from_str(string("tree_gen")?, true)
+                .map_err(::anyhow::Error::msg)
+                .context("Sidecar has an unknown tree generator")?,
+            step_size: number("step_size")?
+                .parse()
+                .with_context(|| invalid("step_size"))?,
+            seed: number("seed")?.parse().with_context(|| invalid("seed"))?,
+            tree_seed: optional_seed("tree_seed")?,
+            colour_seed: optional_seed("colour_seed")?,
+            x: number("x")?.parse().with_context(|| invalid("x"))?,
+            y: number("y")?.parse().with_context(|| invalid("y"))?,
+        };
+        ensure!((0. ..=1.).contains(&sidecar.x), invalid("x"));
+        ensure!((0. ..=1.).contains(&sidecar.y), invalid("y"));
+        Ok(sidecar)
+    }
+
+    /// Read a sidecar file from disk
+    pub(crate) fn read(path: &Path) -> Result<Sidecar> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read sidecar {}", path.display()))?;
+        Sidecar::from_json(&json).with_context(|| format!("Invalid sidecar {}", path.display()))
+    }
+
+    /// Write the sidecar next to an output image, returning the sidecar's path
+    pub(crate) fn write_beside(&self, image_path: &Path) -> Result<PathBuf> {
+        let path = sidecar_path(image_path);
+        fs::write(&path, self.to_json())
+            .with_context(|| format!("Failed to write sidecar {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// `<output_stem>.lapidary.json` in the same directory as the image
+pub(crate) fn sidecar_path(image_path: &Path) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    image_path.with_file_name(format!("{}.lapidary.json", stem))
+}
+
+fn arg_name<E: ArgEnum>(value: E) -> &'static str {
+    value
+        .to_possible_value()
+        .expect("generators are never skipped")
+        .get_name()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    /// The number's text, parsed later into whatever type the field needs
+    Number(String),
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parse a JSON object whose values are all strings or numbers
+fn parse_flat_object(json: &str) -> Result<Vec<(String, Value)>> {
+    let mut chars = json.chars().peekable();
+    let mut fields = Vec::new();
+    let skip_whitespace = |chars: &mut ::std::iter::Peekable<::std::str::Chars>| {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    };
+    skip_whitespace(&mut chars);
+    ensure!(chars.next() == Some('{'), "Expected a JSON object");
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            ensure!(chars.next() == Some(':'), "Expected `:` after `{}`", key);
+            skip_whitespace(&mut chars);
+            let value = if chars.peek() == Some(&'"') {
+                Value::String(parse_string(&mut chars)?)
+            } else {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                ensure!(
+                    !number.is_empty(),
+                    "Expected a string or number for `{}`",
+                    key
+                );
+                Value::Number(number)
+            };
+            fields.push((key, value));
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => bail!("Expected `,` or `}}` in JSON object"),
+            }
+        }
+    }
+    skip_whitespace(&mut chars);
+    ensure!(chars.next().is_none(), "Unexpected text after JSON object");
+    Ok(fields)
+}
+
+fn parse_string(chars: &mut impl Iterator<Item = char>) -> Result<String> {
+    ensure!(chars.next() == Some('"'), "Expected a JSON string");
+    let mut out = String::new();
+    loop {
+        match chars.next().context("Unterminated JSON string")? {
+            '"' => return Ok(out),
+            '\\' => match chars.next().context("Unterminated JSON string")? {
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                '/' => out.push('/'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'u' => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .context("Invalid unicode escape in JSON string")?;
+                    out.push(code);
+                }
+                c => bail!("Invalid escape `\\{}` in JSON string", c),
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::clap::{CommandFactory, FromArgMatches, ValueSource};
+
+    fn sidecar() -> Sidecar {
+        Sidecar {
+            version: "0.1.0".into(),
+            width: 320,
+            height: 200,
+            colour_gen: ColourGen::Hsv,
+            tree_gen: TreeGen::Wilson,
+            step_size: 3,
+            seed: (1 << 53) + 1,
+            tree_seed: Some(u64::MAX),
+            colour_seed: None,
+            x: 0.25,
+            y: 1.,
+        }
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let sidecar = sidecar();
+        assert_eq!(Sidecar::from_json(&sidecar.to_json()).unwrap(), sidecar);
+        let swapped = Sidecar {
+            tree_seed: None,
+            colour_seed: Some(u64::MAX - 1),
+            ..sidecar
+        };
+        assert_eq!(Sidecar::from_json(&swapped.to_json()).unwrap(), swapped);
+    }
+
+    #[test]
+    fn missing_and_mistyped_fields_are_rejected() {
+        let json = sidecar().to_json();
+        let without_width = json.replace("  \"width\": 320,\n", "");
+        let error = Sidecar::from_json(&without_width).unwrap_err();
+        assert_eq!(error.to_string(), "Sidecar is missing `width`");
+        let quoted_seed = json.replace("9007199254740993", "\"9007199254740993\"");
+        let error = Sidecar::from_json(&quoted_seed).unwrap_err();
+        assert_eq!(error.to_string(), "Sidecar field `seed` should be a number");
+        let numeric_gen = json.replace("\"hsv\"", "2");
+        let error = Sidecar::from_json(&numeric_gen).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Sidecar field `colour_gen` should be a string"
+        );
+        let negative_width = json.replace("320", "-320");
+        let error = Sidecar::from_json(&negative_width).unwrap_err();
+        assert_eq!(error.to_string(), "Sidecar field `width` is out of range");
+    }
+
+    #[test]
+    fn command_line_beats_the_sidecar() {
+        let matches = Cli::command()
+            .try_get_matches_from(["lapidary", "-S", "5", "-H", "10", "out.png"])
+            .unwrap();
+        let mut args = Cli::from_arg_matches(&matches).unwrap();
+        let sidecar = sidecar();
+        sidecar.apply(&mut args, |id| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        });
+        assert_eq!(args.seed, Some(5));
+        assert_eq!(args.height, 10);
+        assert_eq!(args.width, sidecar.width);
+        assert_eq!(args.colour_gen, sidecar.colour_gen);
+        assert_eq!(args.tree_seed, sidecar.tree_seed);
+    }
+}
@@ -0,0 +1,58 @@
+use ::lapidary::ColourChannel;
+
+#[test]
+fn wrapping_offset_carries_on_from_the_other_end() {
+    assert_eq!(255u8.wrapping_offset(1), 0);
+    assert_eq!(0u8.wrapping_offset(-1), 255);
+    assert_eq!(250u8.wrapping_offset(10), 4);
+    assert_eq!(5u8.wrapping_offset(-10), 251);
+    assert_eq!(0u8.wrapping_offset(256), 0);
+    assert_eq!(255u8.wrapping_offset(0), 255);
+    assert_eq!(u16::MAX.wrapping_offset(1), 0);
+    assert_eq!(0u16.wrapping_offset(-1), u16::MAX);
+}
+
+#[test]
+fn mirroring_offset_bounces_off_either_end() {
+    assert_eq!(255u8.mirroring_offset(1), 254);
+    assert_eq!(0u8.mirroring_offset(-1), 1);
+    assert_eq!(250u8.mirroring_offset(10), 250);
+    assert_eq!(5u8.mirroring_offset(-10), 5);
+    assert_eq!(0u8.mirroring_offset(255), 255);
+    assert_eq!(0u8.mirroring_offset(510), 0);
+    assert_eq!(255u8.mirroring_offset(-255), 0);
+    assert_eq!(u16::MAX.mirroring_offset(1), u16::MAX - 1);
+    assert_eq!(0u16.mirroring_offset(-1), 1);
+}
+
+#[test]
+fn offsets_match_single_steps() {
+    for start in [0u8, 1, 127, 254, 255] {
+        let (mut wrapped, mut mirrored, mut rising) = (start, start, true);
+        for offset in 1..=600 {
+            wrapped = wrapped.wrapping_add(1);
+            if mirrored == u8::MAX {
+                rising = false;
+            } else if mirrored == 0 {
+                rising = true;
+            }
+            mirrored = if rising { mirrored + 1 } else { mirrored - 1 };
+            assert_eq!(
+                start.wrapping_offset(offset),
+                wrapped,
+                "{} + {}",
+                start,
+                offset
+            );
+            assert_eq!(
+                start.mirroring_offset(offset),
+                mirrored,
+                "{} + {}",
+                start,
+                offset
+            );
+        }
+        assert_eq!(start.wrapping_offset(0), start);
+        assert_eq!(start.mirroring_offset(0), start);
+    }
+}
@@ -0,0 +1,49 @@
+use ::lapidary::{generate, ColourGen, GeneratorConfig};
+
+fn config(colour_gen: ColourGen) -> GeneratorConfig {
+    GeneratorConfig::new(16, 16)
+        .seed(1)
+        .colour_gen(colour_gen)
+        .with_progress(false)
+}
+
+#[test]
+fn bad_hsv_steps_are_rejected() {
+    for (hue, sat, val) in [
+        (-1., 0.05, 0.05),
+        (10., -0.05, 0.05),
+        (10., 0.05, -0.05),
+        (f32::INFINITY, 0.05, 0.05),
+        (10., f32::NAN, 0.05),
+    ] {
+        for colour_gen in [ColourGen::Hsv, ColourGen::Hue] {
+            let result = generate(&config(colour_gen).hsv_steps(hue, sat, val));
+            assert!(result.is_err(), "{:?} {},{},{}", colour_gen, hue, sat, val);
+        }
+    }
+    generate(&config(ColourGen::Hsv).hsv_steps(0., 0., 0.)).unwrap();
+}
+
+#[test]
+fn direction_hue_amounts_must_be_fractions() {
+    for (saturation, value, blend) in [(1.5, 0.9, 0.8), (0.8, -0.1, 0.8), (0.8, 0.9, f32::NAN)] {
+        let config = config(ColourGen::DirectionHue).direction_hue(saturation, value, blend);
+        assert!(
+            generate(&config).is_err(),
+            "{},{},{}",
+            saturation,
+            value,
+            blend
+        );
+    }
+    generate(&config(ColourGen::DirectionHue).direction_hue(0., 1., 1.)).unwrap();
+}
+
+#[test]
+fn blend_factor_must_be_a_fraction() {
+    for blend_factor in [-0.5, 1.5, f32::NAN] {
+        let config = config(ColourGen::Blend).blend_factor(blend_factor);
+        assert!(generate(&config).is_err(), "{}", blend_factor);
+    }
+    generate(&config(ColourGen::Blend).blend_factor(1.)).unwrap();
+}
@@ -0,0 +1,19 @@
+//! Helpers shared by the integration tests
+
+// each test binary only uses some of these
+#![allow(dead_code)]
+
+use ::std::{env, path::PathBuf, process::Command};
+
+/// A path in the temporary directory that no other test uses
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("lapidary-{}-{}", ::std::process::id(), name))
+}
+
+/// The lapidary binary, set up for a small image with a fixed seed
+pub fn lapidary(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lapidary"));
+    command.args(["-W", "32", "-H", "24", "-S", "1"]);
+    command.args(args);
+    command
+}
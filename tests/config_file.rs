@@ -0,0 +1,52 @@
+mod common;
+
+use ::image::GenericImageView;
+use ::std::fs;
+use common::{lapidary, temp_path};
+
+/// Runs lapidary with a config file holding `config`, returning its stderr if
+/// it failed
+fn run_with_config(name: &str, config: &str, args: &[&str]) -> Result<(), String> {
+    let config_path = temp_path(&format!("{}.toml", name));
+    let image = temp_path(&format!("{}.png", name));
+    fs::write(&config_path, config).unwrap();
+    let output = lapidary(&["--config", config_path.to_str().unwrap()])
+        .args(args)
+        .arg(&image)
+        .output()
+        .unwrap();
+    fs::remove_file(&config_path).ok();
+    fs::remove_file(&image).ok();
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8(output.stderr).unwrap())
+    }
+}
+
+#[test]
+fn step_size_too_big_for_the_colour_generator_names_its_line() {
+    let config = "colour_gen = \"hsv\"\nstep_size = 300\n";
+    let error = run_with_config("big-step", config, &[]).unwrap_err();
+    assert!(error.contains("Line 2: `step_size`"), "{}", error);
+    // the same step size is fine once the depth allows it
+    let config = "colour_gen = \"rand\"\ndepth = 16\nstep_size = 300\n";
+    run_with_config("big-step-16", config, &[]).unwrap();
+}
+
+#[test]
+fn command_line_beats_the_config_file() {
+    let config_path = temp_path("override.toml");
+    let image = temp_path("override.png");
+    // lapidary() gives the size on the command line
+    fs::write(&config_path, "width = 20\nheight = 10\n").unwrap();
+    let output = lapidary(&["--config", config_path.to_str().unwrap()])
+        .arg(&image)
+        .output()
+        .unwrap();
+    let decoded = ::image::open(&image);
+    fs::remove_file(&config_path).ok();
+    fs::remove_file(&image).ok();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(decoded.unwrap().dimensions(), (32, 24));
+}
@@ -0,0 +1,21 @@
+use ::lapidary::{generate, GeneratorConfig, Interrupt};
+
+fn config() -> GeneratorConfig {
+    GeneratorConfig::new(40, 30).seed(5).with_progress(false)
+}
+
+#[test]
+fn unstopped_interrupt_leaves_image_unchanged() {
+    let interrupt = Interrupt::new();
+    let image = generate(&config().interrupt(interrupt.clone())).unwrap();
+    assert_eq!(image, generate(&config()).unwrap());
+    // colouring has finished, so there's nothing left to stop
+    assert!(!interrupt.stop());
+}
+
+#[test]
+fn stopping_before_colouring_fails() {
+    let interrupt = Interrupt::new();
+    assert!(!interrupt.stop());
+    assert!(generate(&config().interrupt(interrupt)).is_err());
+}
@@ -0,0 +1,24 @@
+use ::lapidary::{generate, GeneratorConfig, TreeGen};
+use ::std::collections::HashSet;
+
+/// Colouring walks the tree with an explicit stack, so even the long branches
+/// of large images can't overflow a worker thread's stack
+#[test]
+#[ignore = "slow without optimisations"]
+fn large_deep_trees_are_coloured() {
+    for tree_gen in [TreeGen::Prim, TreeGen::Dfs] {
+        let config = GeneratorConfig::new(2000, 2000)
+            .seed(42)
+            .tree_gen(tree_gen)
+            .with_progress(false);
+        let image = generate(&config).unwrap();
+        assert_eq!(image.dimensions(), (2000, 2000));
+        let colours = image.pixels().collect::<HashSet<_>>();
+        assert!(
+            colours.len() > 256,
+            "{:?} gave only {} colours",
+            tree_gen,
+            colours.len()
+        );
+    }
+}
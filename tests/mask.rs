@@ -0,0 +1,35 @@
+mod common;
+
+use ::clap::ArgEnum;
+use ::image::{Luma, Rgb};
+use ::lapidary::{generate, ColourGen, GeneratorConfig, TreeGen};
+use ::std::fs;
+use common::temp_path;
+
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const BACKGROUND: Rgb<u8> = Rgb([200, 0, 50]);
+
+#[test]
+fn masked_pixels_keep_the_background() {
+    let path = temp_path("corner-mask.png");
+    ::image::GrayImage::from_fn(10, 10, |x, y| Luma([if x < 5 && y < 5 { 0 } else { 255 }]))
+        .save(&path)
+        .unwrap();
+    for &tree_gen in TreeGen::value_variants() {
+        let config = GeneratorConfig::new(10, 10)
+            .seed(4)
+            .tree_gen(tree_gen)
+            .colour_gen(ColourGen::Gradient)
+            .gradient(WHITE, WHITE, 1)
+            .start(1., 1.)
+            .mask(&path)
+            .mask_background(BACKGROUND)
+            .with_progress(false);
+        let image = generate(&config).unwrap_or_else(|e| panic!("{:?} failed: {:#}", tree_gen, e));
+        for (x, y, &pixel) in image.enumerate_pixels() {
+            let expected = if x < 5 && y < 5 { BACKGROUND } else { WHITE };
+            assert_eq!(pixel, expected, "{:?} at {},{}", tree_gen, x, y);
+        }
+    }
+    fs::remove_file(&path).ok();
+}
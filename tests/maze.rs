@@ -0,0 +1,69 @@
+use ::lapidary::{generate_maze, generate_maze_timed, render_maze, GeneratorConfig, Neighbours};
+
+/// The maze as rows of `#` for walls and spaces for cells and gaps
+fn draw(width: usize, height: usize, tree: &[Neighbours]) -> Vec<String> {
+    let maze = render_maze(width, height, tree).unwrap();
+    maze.rows()
+        .map(|row| {
+            row.map(|pixel| match pixel.0 {
+                [0] => '#',
+                [255] => ' ',
+                [other] => panic!("maze pixel {} isn't black or white", other),
+            })
+            .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn walls_are_knocked_out_between_joined_cells() {
+    // the bottom row joined up, with every cell of the top row hanging off it
+    let tree = [
+        Neighbours::SOUTH,
+        Neighbours::SOUTH,
+        Neighbours::SOUTH,
+        Neighbours::NORTH | Neighbours::EAST,
+        Neighbours::NORTH | Neighbours::EAST | Neighbours::WEST,
+        Neighbours::NORTH | Neighbours::WEST,
+    ];
+    assert_eq!(
+        draw(3, 2, &tree),
+        ["#######", "# # # #", "# # # #", "#     #", "#######"]
+    );
+}
+
+#[test]
+fn wrapped_edges_open_both_borders() {
+    // the left pixel is joined to the right one around the side of the image
+    let tree = [
+        Neighbours::EAST | Neighbours::WEST,
+        Neighbours::WEST,
+        Neighbours::EAST,
+    ];
+    assert_eq!(draw(3, 1, &tree), ["#######", "    #  ", "#######"]);
+}
+
+#[test]
+fn diagonal_edges_are_rejected() {
+    let tree = [
+        Neighbours::SOUTHEAST,
+        Neighbours::empty(),
+        Neighbours::empty(),
+        Neighbours::NORTHWEST,
+    ];
+    assert!(render_maze(2, 2, &tree).is_err());
+}
+
+#[test]
+fn tree_must_match_dimensions() {
+    assert!(render_maze(2, 2, &[Neighbours::empty(); 3]).is_err());
+}
+
+#[test]
+fn maze_timings_split_the_tree_from_the_drawing() {
+    let config = GeneratorConfig::new(64, 48).seed(5).with_progress(false);
+    let (maze, timings) = generate_maze_timed(&config).unwrap();
+    assert!(maze == generate_maze(&config).unwrap());
+    assert!(timings.tree_gen > ::std::time::Duration::ZERO);
+    assert!(timings.colour > ::std::time::Duration::ZERO);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use ::image::{GenericImageView, ImageFormat};
+use ::std::fs;
+use common::{lapidary, temp_path};
+
+#[test]
+fn format_overrides_the_extension() {
+    let image = temp_path("really-a-png.jpg");
+    let output = lapidary(&["--format", "png", image.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let bytes = fs::read(&image);
+    fs::remove_file(&image).ok();
+    assert!(output.status.success(), "{:?}", output);
+    let bytes = bytes.unwrap();
+    assert_eq!(::image::guess_format(&bytes).unwrap(), ImageFormat::Png);
+    let decoded = ::image::load_from_memory(&bytes).unwrap();
+    assert_eq!(decoded.dimensions(), (32, 24));
+}
+
+#[test]
+fn extension_picks_the_format_by_default() {
+    let image = temp_path("by-extension.jpg");
+    let output = lapidary(&[image.to_str().unwrap()]).output().unwrap();
+    let bytes = fs::read(&image);
+    fs::remove_file(&image).ok();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        ::image::guess_format(&bytes.unwrap()).unwrap(),
+        ImageFormat::Jpeg
+    );
+}
+
+#[test]
+fn webp_format_is_rejected() {
+    let image = temp_path("never-written.webp");
+    let output = lapidary(&["--format", "webp", image.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!image.exists());
+}
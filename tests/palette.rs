@@ -0,0 +1,19 @@
+use ::image::Rgb;
+use ::lapidary::{generate, ColourGen, GeneratorConfig};
+use ::std::collections::HashSet;
+
+#[test]
+fn every_pixel_is_a_palette_colour() {
+    let palette = [Rgb([0, 0, 0]), Rgb([255, 128, 0]), Rgb([40, 90, 250])];
+    for seed in 0..4 {
+        let config = GeneratorConfig::new(64, 48)
+            .seed(seed)
+            .colour_gen(ColourGen::Palette)
+            .palette(palette)
+            .with_progress(false);
+        let image = generate(&config).unwrap();
+        let colours = image.pixels().copied().collect::<HashSet<_>>();
+        // the colours are far apart, but joining them lets the walk reach all
+        assert_eq!(colours, HashSet::from(palette), "seed {}", seed);
+    }
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use ::std::{env, fs, process::Command};
+use common::{lapidary, temp_path};
+
+#[test]
+fn progress_output_none_writes_nothing() {
+    let image = temp_path("none.png");
+    let output = lapidary(&["--progress-output", "none", image.to_str().unwrap()])
+        .output()
+        .unwrap();
+    fs::remove_file(&image).ok();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output.stdout);
+    assert!(output.stderr.is_empty(), "{:?}", output.stderr);
+}
+
+#[test]
+fn progress_output_file_gets_each_stage() {
+    let image = temp_path("file.png");
+    let progress = temp_path("progress.txt");
+    let output = lapidary(&[
+        "--progress-output",
+        progress.to_str().unwrap(),
+        image.to_str().unwrap(),
+    ])
+    .output()
+    .unwrap();
+    let log = fs::read_to_string(&progress);
+    fs::remove_file(&image).ok();
+    fs::remove_file(&progress).ok();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output.stdout);
+    assert!(output.stderr.is_empty(), "{:?}", output.stderr);
+    let log = log.unwrap();
+    assert!(log.contains("Plotting pixels - 100% done"), "{}", log);
+}
+
+#[test]
+fn progress_output_stdout_conflicts_with_image_on_stdout() {
+    let output = lapidary(&["--progress-output", "stdout", "--no-save"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn random_seed_is_printed_unless_quiet() {
+    let image = temp_path("seed.png");
+    let run = |quiet: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_lapidary"));
+        command.args(["-W", "16", "-H", "16", image.to_str().unwrap()]);
+        if quiet {
+            command.arg("--quiet");
+        }
+        command.output().unwrap()
+    };
+    let (loud, quiet) = (run(false), run(true));
+    fs::remove_file(&image).ok();
+    assert!(loud.status.success(), "{:?}", loud);
+    let stderr = String::from_utf8(loud.stderr).unwrap();
+    assert!(
+        stderr.lines().any(|line| line
+            .strip_prefix("seed: ")
+            .is_some_and(|seed| seed.parse::<u64>().is_ok())),
+        "{}",
+        stderr
+    );
+    assert!(quiet.status.success(), "{:?}", quiet);
+    assert!(quiet.stderr.is_empty(), "{:?}", quiet.stderr);
+}
@@ -0,0 +1,64 @@
+use ::lapidary::{prune_edges, Neighbours, Progress};
+
+const WIDTH: usize = 5;
+const HEIGHT: usize = 4;
+
+/// A fully connected grid with the edges pruned
+fn pruned(width: usize, height: usize) -> Vec<Neighbours> {
+    let mut grid = vec![Neighbours::all(); width * height];
+    prune_edges(width, height, Progress::new(false), &mut grid).unwrap();
+    grid
+}
+
+#[test]
+fn top_row_has_no_northward_edges() {
+    let grid = pruned(WIDTH, HEIGHT);
+    for point in &grid[..WIDTH] {
+        assert!(!point.intersects(Neighbours::NORTHWARD), "{:?}", point);
+    }
+}
+
+#[test]
+fn bottom_row_has_no_southward_edges() {
+    let grid = pruned(WIDTH, HEIGHT);
+    for point in &grid[(HEIGHT - 1) * WIDTH..] {
+        assert!(!point.intersects(Neighbours::SOUTHWARD), "{:?}", point);
+    }
+}
+
+#[test]
+fn left_column_has_no_westward_edges() {
+    let grid = pruned(WIDTH, HEIGHT);
+    for point in grid.iter().step_by(WIDTH) {
+        assert!(!point.intersects(Neighbours::WESTWARD), "{:?}", point);
+    }
+}
+
+#[test]
+fn right_column_has_no_eastward_edges() {
+    let grid = pruned(WIDTH, HEIGHT);
+    for point in grid.iter().skip(WIDTH - 1).step_by(WIDTH) {
+        assert!(!point.intersects(Neighbours::EASTWARD), "{:?}", point);
+    }
+}
+
+#[test]
+fn interior_is_unaffected() {
+    let grid = pruned(WIDTH, HEIGHT);
+    for row in 1..HEIGHT - 1 {
+        for col in 1..WIDTH - 1 {
+            assert_eq!(
+                grid[row * WIDTH + col],
+                Neighbours::all(),
+                "{}, {}",
+                row,
+                col
+            );
+        }
+    }
+}
+
+#[test]
+fn single_pixel_has_no_edges() {
+    assert_eq!(pruned(1, 1), vec![Neighbours::empty()]);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use ::clap::ArgEnum;
+use ::image::Rgb;
+use ::lapidary::{generate, ColourGen, GeneratorConfig, TreeGen};
+use common::temp_path;
+
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Every pixel is coloured white when it's reached, leaving any that aren't
+/// black
+fn config(width: u32, height: u32, tree_gen: TreeGen) -> GeneratorConfig {
+    GeneratorConfig::new(width, height)
+        .seed(1)
+        .tree_gen(tree_gen)
+        .colour_gen(ColourGen::Gradient)
+        .gradient(WHITE, WHITE, 1)
+        .with_progress(false)
+}
+
+#[test]
+fn every_tree_generator_fills_tiny_images() {
+    for &tree_gen in TreeGen::value_variants() {
+        for (width, height) in [(1, 1), (1, 5), (5, 1), (2, 2)] {
+            let image = generate(&config(width, height, tree_gen)).unwrap_or_else(|e| {
+                panic!("{:?} failed at {}x{}: {:#}", tree_gen, width, height, e)
+            });
+            assert_eq!(image.dimensions(), (width, height));
+            for (x, y, &pixel) in image.enumerate_pixels() {
+                assert_eq!(
+                    pixel, WHITE,
+                    "{:?} missed {},{} at {}x{}",
+                    tree_gen, x, y, width, height
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn zero_dimensions_are_rejected() {
+    for (width, height) in [(0, 5), (5, 0), (0, 0)] {
+        assert!(generate(&config(width, height, TreeGen::Prim)).is_err());
+    }
+}
+
+#[test]
+fn tiled_trees_are_rejected_on_untiled_images() {
+    let path = temp_path("tiled.tree");
+    generate(&config(8, 8, TreeGen::Prim).tile(true).save_tree(&path)).unwrap();
+    let result = generate(&config(8, 8, TreeGen::Prim).load_tree(&path));
+    ::std::fs::remove_file(&path).ok();
+    assert!(result.is_err());
+}
+
+#[test]
+fn supersampled_images_are_scaled_back_down() {
+    for (width, height, factor) in [(100, 100, 2), (7, 5, 3), (1, 1, 4)] {
+        let image = generate(&config(width, height, TreeGen::Prim).supersample(factor)).unwrap();
+        assert_eq!(image.dimensions(), (width, height), "factor {}", factor);
+        assert!(image.pixels().all(|&pixel| pixel == WHITE));
+    }
+}
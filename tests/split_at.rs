@@ -0,0 +1,100 @@
+use ::anyhow::Result;
+use ::image::Rgb;
+use ::lapidary::{
+    generate, generate_with, GenColour, GenTree, GeneratorConfig, Neighbours, Progress,
+};
+
+const WIDTH: usize = 1414;
+const HEIGHT: usize = 1416;
+
+/// A comb: the top row and every even column form the spine, and every other
+/// pixel is a leaf hanging off the spine to its west, for just over a million
+/// leaves
+struct Comb;
+
+impl GenTree for Comb {
+    fn tree(&mut self, width: usize, height: usize, _: Progress) -> Result<Vec<Neighbours>> {
+        let mut tree = vec![Neighbours::empty(); width * height];
+        let mut join = |from: usize, to: usize, forward: Neighbours, back: Neighbours| {
+            tree[from] |= forward;
+            tree[to] |= back;
+        };
+        for col in 1..width {
+            join(col - 1, col, Neighbours::EAST, Neighbours::WEST);
+        }
+        for row in 1..height {
+            for col in 0..width {
+                let point = row * width + col;
+                if col % 2 == 0 {
+                    join(point - width, point, Neighbours::SOUTH, Neighbours::NORTH);
+                } else {
+                    join(point - 1, point, Neighbours::EAST, Neighbours::WEST);
+                }
+            }
+        }
+        Ok(tree)
+    }
+}
+
+/// Paints every pixel but the start point the same colour
+#[derive(Clone, Copy)]
+struct Paint(Rgb<u8>);
+
+impl GenColour<Rgb<u8>> for Paint {
+    fn colour(&mut self, _: Rgb<u8>, _: Neighbours, _: (u32, u32), _: (u32, u32)) -> Rgb<u8> {
+        self.0
+    }
+
+    fn new(&mut self) -> Self {
+        *self
+    }
+}
+
+#[test]
+fn million_leaf_comb_is_coloured_at_any_split() {
+    let paint = Rgb([10, 200, 30]);
+    let leaves = (HEIGHT - 1) * (WIDTH / 2);
+    assert!(leaves > 1_000_000);
+    for (split_at, max_fanout) in [(1, 1), (256, 8), (usize::MAX, 8)] {
+        let config = GeneratorConfig::new(WIDTH as u32, HEIGHT as u32)
+            .start(0., 0.)
+            .split_at(split_at)
+            .max_fanout(max_fanout)
+            .with_progress(false);
+        let image = generate_with(&config, Comb, Paint(paint)).unwrap();
+        assert!(
+            image.pixels().skip(1).all(|&pixel| pixel == paint),
+            "split at {} with fanout {}",
+            split_at,
+            max_fanout
+        );
+    }
+}
+
+#[test]
+fn split_point_and_fanout_dont_change_the_image() {
+    let config = |split_at, max_fanout| {
+        GeneratorConfig::new(512, 512)
+            .seed(11)
+            .split_at(split_at)
+            .max_fanout(max_fanout)
+            .with_progress(false)
+    };
+    let image = generate(&config(usize::MAX, 8)).unwrap();
+    for (split_at, max_fanout) in [(1, 1), (2, 8), (64, 2), (256, 8), (4096, 8)] {
+        assert!(
+            generate(&config(split_at, max_fanout)).unwrap() == image,
+            "image differs when split at {} with fanout {}",
+            split_at,
+            max_fanout
+        );
+    }
+}
+
+#[test]
+fn zero_fanout_is_rejected() {
+    let config = GeneratorConfig::new(8, 8)
+        .max_fanout(0)
+        .with_progress(false);
+    assert!(generate(&config).is_err());
+}
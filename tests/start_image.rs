@@ -0,0 +1,59 @@
+mod common;
+
+use ::image::{Rgb, RgbImage};
+use ::lapidary::{generate, ColourGen, GeneratorConfig, TreeGen};
+use ::std::fs;
+use common::temp_path;
+
+/// Colours never change along the tree, so every pixel keeps the colour of
+/// the start point it grew from
+fn config(width: u32, height: u32) -> GeneratorConfig {
+    GeneratorConfig::new(width, height)
+        .seed(2)
+        .tree_gen(TreeGen::Prim)
+        .colour_gen(ColourGen::Rand)
+        .step_size(0)
+        .with_progress(false)
+}
+
+#[test]
+fn each_start_point_takes_its_reference_colour() {
+    let (left, right) = (Rgb([200, 40, 10]), Rgb([0, 0, 0]));
+    let path = temp_path("halves.png");
+    RgbImage::from_fn(20, 10, |x, _| if x < 10 { left } else { right })
+        .save(&path)
+        .unwrap();
+    let image = generate(
+        &config(20, 10)
+            .start(0., 0.)
+            .extra_start(1., 1.)
+            .start_image(&path),
+    );
+    fs::remove_file(&path).ok();
+    let image = image.unwrap();
+    assert_eq!(*image.get_pixel(0, 0), left);
+    assert_eq!(*image.get_pixel(19, 9), right);
+    assert!(image.pixels().all(|&pixel| pixel == left || pixel == right));
+}
+
+#[test]
+fn reference_images_of_another_size_are_scaled() {
+    let colour = Rgb([30, 90, 150]);
+    let path = temp_path("small.png");
+    RgbImage::from_pixel(3, 2, colour).save(&path).unwrap();
+    let image = generate(&config(16, 12).start(0.5, 0.5).start_image(&path));
+    fs::remove_file(&path).ok();
+    assert!(image.unwrap().pixels().all(|&pixel| pixel == colour));
+}
+
+#[test]
+fn x_picks_the_column_and_y_the_row() {
+    let marked = Rgb([255, 0, 0]);
+    let path = temp_path("marked.png");
+    let mut reference = RgbImage::new(100, 200);
+    reference.put_pixel(50, 0, marked);
+    reference.save(&path).unwrap();
+    let image = generate(&config(100, 200).start(0.5, 0.).start_image(&path));
+    fs::remove_file(&path).ok();
+    assert!(image.unwrap().pixels().all(|&pixel| pixel == marked));
+}
@@ -0,0 +1,40 @@
+use ::lapidary::{generate, ColourGen, GeneratorConfig, Symmetry, TreeGen};
+
+fn config(width: u32, height: u32, symmetry: Symmetry) -> GeneratorConfig {
+    GeneratorConfig::new(width, height)
+        .seed(7)
+        .tree_gen(TreeGen::Prim)
+        .colour_gen(ColourGen::Rand)
+        .start(0.5, 0.5)
+        .symmetry(symmetry)
+        .with_progress(false)
+}
+
+#[test]
+fn horizontal_symmetry_mirrors_columns() {
+    for (width, height) in [(20, 9), (21, 9), (1, 3)] {
+        let image = generate(&config(width, height, Symmetry::Horizontal)).unwrap();
+        assert_eq!(image.dimensions(), (width, height));
+        for (x, y, pixel) in image.enumerate_pixels() {
+            assert_eq!(
+                pixel,
+                image.get_pixel(width - 1 - x, y),
+                "{},{} doesn't match at {}x{}",
+                x,
+                y,
+                width,
+                height
+            );
+        }
+    }
+}
+
+#[test]
+fn quad_symmetry_mirrors_rows_and_columns() {
+    let (width, height) = (15, 12);
+    let image = generate(&config(width, height, Symmetry::Quad)).unwrap();
+    for (x, y, pixel) in image.enumerate_pixels() {
+        assert_eq!(pixel, image.get_pixel(width - 1 - x, y));
+        assert_eq!(pixel, image.get_pixel(x, height - 1 - y));
+    }
+}
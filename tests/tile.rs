@@ -0,0 +1,103 @@
+use ::clap::ArgEnum;
+use ::image::{Rgb, RgbImage};
+use ::lapidary::{generate, ColourGen, GeneratorConfig, TreeGen};
+
+const SIZE: u32 = 64;
+
+fn config(tree_gen: TreeGen, seed: u64) -> GeneratorConfig {
+    sized_config(SIZE, SIZE, tree_gen, seed)
+}
+
+fn sized_config(width: u32, height: u32, tree_gen: TreeGen, seed: u64) -> GeneratorConfig {
+    GeneratorConfig::new(width, height)
+        .seed(seed)
+        .tree_gen(tree_gen)
+        .colour_gen(ColourGen::Rand)
+        .tile(true)
+        .with_progress(false)
+}
+
+/// Sum of the absolute differences between two colours' channels
+fn delta(Rgb(a): Rgb<u8>, Rgb(b): Rgb<u8>) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, b)| f64::from(a.abs_diff(b)))
+        .sum()
+}
+
+/// Mean colour difference between each pixel and the ones to its right and
+/// below, split into pairs inside the image and pairs across the seams
+fn mean_deltas(image: &RgbImage) -> (f64, f64) {
+    let (mut interior, mut seam) = ((0., 0), (0., 0));
+    for (x, y, &pixel) in image.enumerate_pixels() {
+        for (x2, y2) in [((x + 1) % SIZE, y), (x, (y + 1) % SIZE)] {
+            let pair = if x2 < x || y2 < y {
+                &mut seam
+            } else {
+                &mut interior
+            };
+            pair.0 += delta(pixel, *image.get_pixel(x2, y2));
+            pair.1 += 1;
+        }
+    }
+    (
+        interior.0 / f64::from(interior.1),
+        seam.0 / f64::from(seam.1),
+    )
+}
+
+/// Tree generators that can join opposite edges of the image
+fn tileable() -> impl Iterator<Item = TreeGen> {
+    TreeGen::value_variants()
+        .iter()
+        .copied()
+        .filter(|tree_gen| {
+            !matches!(
+                tree_gen,
+                TreeGen::Test | TreeGen::Spiral | TreeGen::Quad | TreeGen::Hilbert
+            )
+        })
+}
+
+#[test]
+fn tiled_trees_stay_on_small_images() {
+    for tree_gen in tileable() {
+        for (width, height) in [(3, 3), (3, 8), (8, 3), (5, 4)] {
+            for seed in 0..4 {
+                let image =
+                    generate(&sized_config(width, height, tree_gen, seed)).unwrap_or_else(|e| {
+                        panic!("{:?} failed at {}x{}: {:#}", tree_gen, width, height, e)
+                    });
+                assert_eq!(image.dimensions(), (width, height));
+            }
+        }
+    }
+}
+
+#[test]
+fn tiled_images_have_no_seams() {
+    for tree_gen in tileable() {
+        let (mut interior, mut seam) = (0., 0.);
+        for seed in 0..4 {
+            let image = generate(&config(tree_gen, seed)).unwrap();
+            assert_eq!(image.dimensions(), (SIZE, SIZE));
+            let deltas = mean_deltas(&image);
+            interior += deltas.0;
+            seam += deltas.1;
+        }
+        assert!(
+            seam < interior * 1.5,
+            "{:?} seams differ by {} against {} inside",
+            tree_gen,
+            seam / 4.,
+            interior / 4.
+        );
+    }
+}
+
+#[test]
+fn untiled_generators_refuse_to_tile() {
+    for tree_gen in [TreeGen::Spiral, TreeGen::Quad, TreeGen::Hilbert] {
+        assert!(generate(&config(tree_gen, 0)).is_err(), "{:?}", tree_gen);
+    }
+}